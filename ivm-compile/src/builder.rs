@@ -0,0 +1,294 @@
+//! A module for building programs using symbolic labels instead of absolute memory pointer indexes.
+//!
+//! # Example
+//!
+//! ```
+//! use ivm_compile::builder::ProgramBuilder;
+//! use ivm_compile::options::ProgramOptions;
+//! use ivm_compile::{Instruction, ReadOperation};
+//!
+//! let mut builder = ProgramBuilder::new(4);
+//!
+//! builder
+//!     .label("loop")
+//!     .instruction_with_refs(
+//!         Instruction::JumpIf(0, ReadOperation::Point(1, 0)),
+//!         [(0, "loop"), (1, "flag")],
+//!     )
+//!     .instruction(Instruction::Return)
+//!     .label("flag")
+//!     .data([0]);
+//!
+//! let program = builder.build(&ProgramOptions::default()).unwrap();
+//! assert_eq!(program.label("loop"), Some(4));
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::options::ProgramOptions;
+use crate::{Compile, Instruction};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildErrorCause {
+    /// A label was referenced, but never defined.
+    UndefinedLabel(String),
+
+    /// A label was defined more than once.
+    DuplicateLabel(String),
+
+    /// A label reference targets a memory pointer index the instruction does not have.
+    ///
+    /// See [Instruction::addresses_mut()].
+    InvalidSlot(usize),
+
+    /// An instruction contains a memory pointer sized value that does not fit the
+    /// [crate::options::MemoryPointerLength], and would be truncated on compile.
+    ///
+    /// See [Instruction::fits()].
+    PointerOverflow,
+}
+
+impl Display for BuildErrorCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UndefinedLabel(name) => write!(f, "undefined label '{name}'"),
+            Self::DuplicateLabel(name) => write!(f, "duplicate label '{name}'"),
+            Self::InvalidSlot(slot) => write!(f, "instruction has no address slot {slot}"),
+            Self::PointerOverflow => write!(f, "memory pointer does not fit the pointer length"),
+        }
+    }
+}
+
+/// An error returned when a program could not be built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildError {
+    entry: usize,
+    cause: BuildErrorCause,
+}
+
+impl BuildError {
+    /// Get the index of the entry at which building failed.
+    ///
+    /// Entries are counted in the order they were added to the [ProgramBuilder], starting at 0.
+    /// For duplicate labels, this is the entry the second definition points to.
+    #[inline]
+    pub const fn entry(&self) -> usize {
+        self.entry
+    }
+
+    /// Get the cause of this error.
+    #[inline]
+    pub const fn cause(&self) -> &BuildErrorCause {
+        &self.cause
+    }
+
+    #[inline]
+    pub const fn new(entry: usize, cause: BuildErrorCause) -> Self {
+        Self { entry, cause }
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at entry {}", self.cause, self.entry)
+    }
+}
+
+impl Error for BuildError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    Instruction(Instruction, Vec<(usize, String)>),
+    Data(Vec<u8>),
+}
+
+impl Entry {
+    fn encoded_len(&self, program_options: &ProgramOptions) -> usize {
+        match self {
+            Self::Instruction(instruction, _) => instruction.encoded_len(program_options),
+            Self::Data(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// The output of [ProgramBuilder::build()].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuiltProgram {
+    bytecode: Vec<u8>,
+    labels: HashMap<String, usize>,
+}
+
+impl BuiltProgram {
+    /// Get the built bytecode.
+    #[inline]
+    pub fn bytecode(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// Consume this program, returning the built bytecode.
+    #[inline]
+    pub fn into_bytecode(self) -> Vec<u8> {
+        self.bytecode
+    }
+
+    /// Get the memory pool index of the given label.
+    #[inline]
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+}
+
+/// A builder for programs containing label definitions and label references.
+///
+/// Labels resolve to absolute memory pool indexes, relative to the base address of the builder.
+/// The base address is the memory pool index the bytecode will be placed at; for a VM created by
+/// `VmInstance::reserve_ivm_ext_x32()`, this is `ivm_ext_x32::REGISTER_RESERVED`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramBuilder {
+    base: usize,
+    entries: Vec<Entry>,
+    labels: Vec<(String, usize)>,
+}
+
+impl ProgramBuilder {
+    /// Get the base address of this builder.
+    #[inline]
+    pub const fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Get the amount of entries (instructions and data) added to this builder.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no entries were added to this builder.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Define a label pointing to the next entry.
+    ///
+    /// Duplicate definitions are reported by [Self::build()].
+    pub fn label<S>(&mut self, name: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.labels.push((name.into(), self.entries.len()));
+        self
+    }
+
+    /// Add an instruction without label references.
+    pub fn instruction(&mut self, instruction: Instruction) -> &mut Self {
+        self.entries
+            .push(Entry::Instruction(instruction, Vec::new()));
+        self
+    }
+
+    /// Add an instruction, replacing some of its memory pointer indexes with label addresses once
+    /// built.
+    ///
+    /// Each reference is a tuple of a slot and a label name. The slot is the index of the memory
+    /// pointer index in [Instruction::addresses_mut()]. The values in referenced slots are ignored.
+    pub fn instruction_with_refs<I, S>(&mut self, instruction: Instruction, refs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (usize, S)>,
+        S: Into<String>,
+    {
+        let refs = refs
+            .into_iter()
+            .map(|(slot, name)| (slot, name.into()))
+            .collect();
+
+        self.entries.push(Entry::Instruction(instruction, refs));
+        self
+    }
+
+    /// Add raw data.
+    pub fn data<I>(&mut self, bytes: I) -> &mut Self
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        self.entries.push(Entry::Data(bytes.into_iter().collect()));
+        self
+    }
+
+    /// Lay out every entry, resolve label references, then compile the program.
+    ///
+    /// The provided [ProgramOptions] decide the encoded length of each instruction. Instructions
+    /// whose memory pointer sized values do not fit the pointer length are rejected instead of
+    /// being truncated.
+    pub fn build(&self, program_options: &ProgramOptions) -> Result<BuiltProgram, BuildError> {
+        let mut offsets = Vec::with_capacity(self.entries.len() + 1);
+        let mut offset = self.base;
+
+        for entry in &self.entries {
+            offsets.push(offset);
+            offset += entry.encoded_len(program_options);
+        }
+        offsets.push(offset);
+
+        let mut labels = HashMap::with_capacity(self.labels.len());
+
+        for (name, entry) in &self.labels {
+            if labels.insert(name.clone(), offsets[*entry]).is_some() {
+                return Err(BuildError::new(
+                    *entry,
+                    BuildErrorCause::DuplicateLabel(name.clone()),
+                ));
+            }
+        }
+
+        let mut bytecode = Vec::with_capacity(offset - self.base);
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            match entry {
+                Entry::Data(bytes) => bytecode.extend_from_slice(bytes),
+                Entry::Instruction(instruction, _)
+                    if !instruction.fits(program_options.ptr_len()) =>
+                {
+                    return Err(BuildError::new(index, BuildErrorCause::PointerOverflow))
+                }
+                Entry::Instruction(instruction, refs) if refs.is_empty() => {
+                    instruction.compile_into(&mut bytecode, program_options)
+                }
+                Entry::Instruction(instruction, refs) => {
+                    let mut instruction = instruction.clone();
+                    let mut slots = instruction.addresses_mut();
+
+                    for (slot, name) in refs {
+                        let address = labels.get(name).ok_or_else(|| {
+                            BuildError::new(index, BuildErrorCause::UndefinedLabel(name.clone()))
+                        })?;
+
+                        **slots
+                            .get_mut(*slot)
+                            .ok_or(BuildError::new(index, BuildErrorCause::InvalidSlot(*slot)))? =
+                            *address;
+                    }
+
+                    if !instruction.fits(program_options.ptr_len()) {
+                        return Err(BuildError::new(index, BuildErrorCause::PointerOverflow));
+                    }
+                    instruction.compile_into(&mut bytecode, program_options);
+                }
+            }
+        }
+
+        Ok(BuiltProgram { bytecode, labels })
+    }
+
+    /// Create a new, empty ProgramBuilder placing its bytecode at the given base address.
+    #[inline]
+    pub const fn new(base: usize) -> Self {
+        Self {
+            base,
+            entries: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+}
@@ -21,6 +21,63 @@ pub const I_CALL: u8 = 5;
 /// See [crate::Instruction::LoadA].
 pub const I_LOAD_A: u8 = 6;
 
+/// See [crate::Instruction::Add].
+pub const I_ADD: u8 = 7;
+
+/// See [crate::Instruction::Sub].
+pub const I_SUB: u8 = 8;
+
+/// See [crate::Instruction::Mul].
+pub const I_MUL: u8 = 9;
+
+/// See [crate::Instruction::Div].
+pub const I_DIV: u8 = 10;
+
+/// See [crate::Instruction::Rem].
+pub const I_REM: u8 = 11;
+
+/// See [crate::Instruction::Neg].
+pub const I_NEG: u8 = 12;
+
+/// See [crate::Instruction::Eq].
+pub const I_EQ: u8 = 13;
+
+/// See [crate::Instruction::Ne].
+pub const I_NE: u8 = 14;
+
+/// See [crate::Instruction::Lt].
+pub const I_LT: u8 = 15;
+
+/// See [crate::Instruction::Le].
+pub const I_LE: u8 = 16;
+
+/// See [crate::Instruction::Gt].
+pub const I_GT: u8 = 17;
+
+/// See [crate::Instruction::Ge].
+pub const I_GE: u8 = 18;
+
+/// See [crate::Instruction::JumpIf].
+pub const I_JUMP_IF: u8 = 19;
+
+/// See [crate::Instruction::JumpIfNot].
+pub const I_JUMP_IF_NOT: u8 = 20;
+
+/// See [crate::Instruction::Pop].
+pub const I_POP: u8 = 21;
+
+/// See [crate::Instruction::Drop].
+pub const I_DROP: u8 = 22;
+
+/// See [crate::Instruction::Dup].
+pub const I_DUP: u8 = 23;
+
+/// See [crate::Instruction::Swap].
+pub const I_SWAP: u8 = 24;
+
+/// See [crate::Instruction::Over].
+pub const I_OVER: u8 = 25;
+
 /// See [crate::ReadOperation::Local].
 pub const RDOP_LOCAL: u8 = 0;
 
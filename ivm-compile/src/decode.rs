@@ -0,0 +1,250 @@
+//! A module for decoding ivm bytecode back into [Instruction]s.
+//!
+//! Decoding is the inverse of [crate::Compile]: compiling a value, then decoding the bytecode with
+//! the same [ProgramOptions] will result in the same value, as long as the value
+//! [fits](crate::Instruction::fits()) the [crate::options::MemoryPointerLength]. Memory pointer
+//! sized values that do not fit are truncated on compile, and decode to the truncated value.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::options::ProgramOptions;
+use crate::{byte_id, Instruction, IntType, ReadOperation};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeErrorCause {
+    /// The bytecode ended in the middle of a value.
+    UnexpectedEnd,
+
+    /// The opcode byte did not match any instruction.
+    UnrecognizedInstruction(u8),
+
+    /// The identifier byte of a read operation was not recognized.
+    UnrecognizedReadOperation(u8),
+
+    /// The identifier byte of an integer type was not recognized.
+    UnrecognizedIntType(u8),
+}
+
+impl Display for DecodeErrorCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of bytecode"),
+            Self::UnrecognizedInstruction(id) => write!(f, "unrecognized instruction '{id:02x}'"),
+            Self::UnrecognizedReadOperation(id) => {
+                write!(f, "unrecognized read operation '{id:02x}'")
+            }
+            Self::UnrecognizedIntType(id) => write!(f, "unrecognized integer type '{id:02x}'"),
+        }
+    }
+}
+
+/// An error returned when bytecode could not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    offset: usize,
+    cause: DecodeErrorCause,
+}
+
+impl DecodeError {
+    /// Get the offset of the byte at which decoding failed.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the cause of this error.
+    #[inline]
+    pub const fn cause(&self) -> &DecodeErrorCause {
+        &self.cause
+    }
+
+    #[inline]
+    pub const fn new(offset: usize, cause: DecodeErrorCause) -> Self {
+        Self { offset, cause }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.cause, self.offset)
+    }
+}
+
+impl Error for DecodeError {}
+
+pub type DecodeResult<T> = Result<(T, usize), DecodeError>;
+
+/// A trait marking a type as being able to be decoded from ivm bytecode.
+pub trait Decode: Sized {
+    /// Decode a value starting at the given offset of the bytecode.
+    ///
+    /// The provided [ProgramOptions] shall be used in the decoding.
+    ///
+    /// Returns a tuple containing the value and the amount of bytes that were read.
+    fn decode(bytes: &[u8], offset: usize, program_options: &ProgramOptions) -> DecodeResult<Self>;
+}
+
+/// A cursor over bytecode, used by the [Decode] implementations.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    program_options: &'a ProgramOptions,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..)
+            .and_then(|bytes| bytes.get(..len))
+            .ok_or(DecodeError::new(
+                self.offset,
+                DecodeErrorCause::UnexpectedEnd,
+            ))?;
+
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn ptr(&mut self) -> Result<usize, DecodeError> {
+        let ptr_len = self.program_options.ptr_len();
+        Ok(ptr_len.to_usize(self.take(ptr_len.get_span())?))
+    }
+
+    fn decode<T>(&mut self) -> Result<T, DecodeError>
+    where
+        T: Decode,
+    {
+        let (value, read) = T::decode(self.bytes, self.offset, self.program_options)?;
+        self.offset += read;
+        Ok(value)
+    }
+
+    fn binary_operands(
+        &mut self,
+    ) -> Result<(IntType, usize, ReadOperation, ReadOperation), DecodeError> {
+        Ok((self.decode()?, self.ptr()?, self.decode()?, self.decode()?))
+    }
+}
+
+impl Decode for IntType {
+    fn decode(
+        bytes: &[u8],
+        offset: usize,
+        _program_options: &ProgramOptions,
+    ) -> DecodeResult<Self> {
+        let byte = *bytes
+            .get(offset)
+            .ok_or(DecodeError::new(offset, DecodeErrorCause::UnexpectedEnd))?;
+
+        match Self::from_byte_identifier(byte) {
+            Some(int_type) => Ok((int_type, 1)),
+            None => Err(DecodeError::new(
+                offset,
+                DecodeErrorCause::UnrecognizedIntType(byte),
+            )),
+        }
+    }
+}
+
+impl Decode for ReadOperation {
+    fn decode(bytes: &[u8], offset: usize, program_options: &ProgramOptions) -> DecodeResult<Self> {
+        let mut reader = Reader {
+            bytes,
+            offset,
+            program_options,
+        };
+
+        let read_op = match reader.byte()? {
+            byte_id::RDOP_LOCAL => {
+                let len = reader.ptr()?;
+                Self::Local(reader.take(len)?.to_vec())
+            }
+            byte_id::RDOP_POINT => Self::Point(reader.ptr()?, reader.ptr()?),
+            id => {
+                return Err(DecodeError::new(
+                    offset,
+                    DecodeErrorCause::UnrecognizedReadOperation(id),
+                ))
+            }
+        };
+        Ok((read_op, reader.offset - offset))
+    }
+}
+
+impl Decode for Instruction {
+    fn decode(bytes: &[u8], offset: usize, program_options: &ProgramOptions) -> DecodeResult<Self> {
+        let mut reader = Reader {
+            bytes,
+            offset,
+            program_options,
+        };
+
+        macro_rules! binary {
+            ($variant:ident) => {{
+                let (int_type, dest, lhs, rhs) = reader.binary_operands()?;
+                Self::$variant(int_type, dest, lhs, rhs)
+            }};
+        }
+
+        let instruction = match reader.byte()? {
+            byte_id::I_JUMP => Self::Jump(reader.ptr()?),
+            byte_id::I_PUSH => Self::Push(reader.decode()?),
+            byte_id::I_MUTATE => Self::Mutate(reader.ptr()?, reader.decode()?),
+            byte_id::I_EXTERN_CALL => Self::ExternCall(reader.ptr()?),
+            byte_id::I_RETURN => Self::Return,
+            byte_id::I_CALL => Self::Call(reader.ptr()?),
+            byte_id::I_LOAD_A => Self::LoadA(reader.decode()?),
+            byte_id::I_ADD => binary!(Add),
+            byte_id::I_SUB => binary!(Sub),
+            byte_id::I_MUL => binary!(Mul),
+            byte_id::I_DIV => binary!(Div),
+            byte_id::I_REM => binary!(Rem),
+            byte_id::I_NEG => Self::Neg(reader.decode()?, reader.ptr()?, reader.decode()?),
+            byte_id::I_EQ => binary!(Eq),
+            byte_id::I_NE => binary!(Ne),
+            byte_id::I_LT => binary!(Lt),
+            byte_id::I_LE => binary!(Le),
+            byte_id::I_GT => binary!(Gt),
+            byte_id::I_GE => binary!(Ge),
+            byte_id::I_JUMP_IF => Self::JumpIf(reader.ptr()?, reader.decode()?),
+            byte_id::I_JUMP_IF_NOT => Self::JumpIfNot(reader.ptr()?, reader.decode()?),
+            byte_id::I_POP => Self::Pop(reader.ptr()?),
+            byte_id::I_DROP => Self::Drop,
+            byte_id::I_DUP => Self::Dup,
+            byte_id::I_SWAP => Self::Swap,
+            byte_id::I_OVER => Self::Over,
+            id => {
+                return Err(DecodeError::new(
+                    offset,
+                    DecodeErrorCause::UnrecognizedInstruction(id),
+                ))
+            }
+        };
+        Ok((instruction, reader.offset - offset))
+    }
+}
+
+/// Decode every instruction of the given bytecode.
+///
+/// Returns a [Vec] of tuples containing the offset of each instruction and the instruction itself.
+/// Decoding stops at the first malformed instruction.
+pub fn decode_all(
+    bytes: &[u8],
+    program_options: &ProgramOptions,
+) -> Result<Vec<(usize, Instruction)>, DecodeError> {
+    let mut res = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let (instruction, read) = Instruction::decode(bytes, offset, program_options)?;
+        res.push((offset, instruction));
+        offset += read;
+    }
+    Ok(res)
+}
@@ -0,0 +1,78 @@
+//! A module for writing and reading complete ivm bytecode images.
+//!
+//! See [crate::options::header_format_doc] for a full guide regarding the ivm bytecode format.
+
+use crate::options::{InvalidHeaderError, ProgramOptions};
+use crate::{version_adapters, Compile};
+
+/// Write the full header of the current compile feature version to the given [Vec].
+///
+/// The execution start is the memory pool index at which the VM will begin execution.
+pub fn write_header(dest: &mut Vec<u8>, options: &ProgramOptions, execution_start: u64) {
+    options.compile_into(dest, options);
+    dest.extend(execution_start.to_le_bytes());
+}
+
+/// A complete ivm bytecode image, consisting of a header and the program body.
+///
+/// Writing an image, then reading it back using [ProgramImage::read()] will always result in the
+/// same image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramImage {
+    options: ProgramOptions,
+    execution_start: u64,
+    body: Vec<u8>,
+}
+
+impl ProgramImage {
+    /// Get the [ProgramOptions] declared in the header of this image.
+    #[inline]
+    pub const fn options(&self) -> &ProgramOptions {
+        &self.options
+    }
+
+    /// Get the execution start declared in the header of this image.
+    #[inline]
+    pub const fn execution_start(&self) -> u64 {
+        self.execution_start
+    }
+
+    /// Get the program body of this image.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Read an image, validating its header.
+    ///
+    /// See [version_adapters::get_program_options()].
+    pub fn read(bytes: &[u8]) -> Result<Self, InvalidHeaderError> {
+        let adapt = version_adapters::get_program_options(bytes)?;
+
+        Ok(Self::new(
+            adapt.options,
+            adapt.function_start,
+            bytes[adapt.header_len..].to_vec(),
+        ))
+    }
+
+    /// Create a new ProgramImage.
+    #[inline]
+    pub const fn new(options: ProgramOptions, execution_start: u64, body: Vec<u8>) -> Self {
+        Self {
+            options,
+            execution_start,
+            body,
+        }
+    }
+}
+
+impl Compile for ProgramImage {
+    /// Write the full header of this image, followed by its body.
+    ///
+    /// The provided [ProgramOptions] are ignored in favor of the options of this image.
+    fn compile_into(&self, dest: &mut Vec<u8>, _program_options: &ProgramOptions) {
+        write_header(dest, &self.options, self.execution_start);
+        dest.extend_from_slice(&self.body);
+    }
+}
@@ -1,9 +1,13 @@
 //! This crate provides a medium-level "instruction" wrapper for ivm bytecode.
 
-use crate::options::ProgramOptions;
+use crate::options::{MemoryPointerLength, ProgramOptions};
 
+pub mod builder;
 pub mod byte_id;
+pub mod decode;
+pub mod image;
 pub mod options;
+pub mod verify;
 pub mod version_adapters;
 
 /// A trait marking a type as being able to be compiled to ivm bytecode.
@@ -23,6 +27,7 @@ pub trait Compile {
 }
 
 /// When the VM encounters an instruction that requires a value, it will perform a read operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ReadOperation {
     /// The bytes are hardcoded in the file, after this point.
     ///
@@ -44,6 +49,34 @@ impl ReadOperation {
             Self::Point(_, _) => byte_id::RDOP_POINT,
         }
     }
+
+    /// Get the amount of bytes this read operation compiles to under the given [ProgramOptions].
+    pub fn encoded_len(&self, program_options: &ProgramOptions) -> usize {
+        let span = program_options.ptr_len().get_span();
+
+        1 + span
+            + match self {
+                Self::Local(v) => v.len(),
+                Self::Point(_, _) => span,
+            }
+    }
+
+    /// Check whether the length and memory pointer index of this read operation fit the given
+    /// [MemoryPointerLength].
+    pub fn fits(&self, ptr_len: &MemoryPointerLength) -> bool {
+        match self {
+            Self::Local(v) => ptr_len.can_fit(v.len()),
+            Self::Point(len, index) => ptr_len.can_fit(*len) && ptr_len.can_fit(*index),
+        }
+    }
+
+    /// Get a mutable reference to the memory pointer index of this read operation, if any.
+    pub fn address_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Self::Local(_) => None,
+            Self::Point(_, index) => Some(index),
+        }
+    }
 }
 
 impl Compile for ReadOperation {
@@ -65,9 +98,87 @@ impl Compile for ReadOperation {
     }
 }
 
+/// The integer type an arithmetic instruction operates on.
+///
+/// Integers are always stored in little endian byte order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+}
+
+impl IntType {
+    /// Get the byte size of this integer type.
+    #[inline]
+    pub const fn get_size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 => 4,
+            Self::I64 | Self::U64 => 8,
+            Self::I128 | Self::U128 => 16,
+        }
+    }
+
+    /// Returns true if this integer type is signed.
+    #[inline]
+    pub const fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            Self::I8 | Self::I16 | Self::I32 | Self::I64 | Self::I128
+        )
+    }
+
+    /// Get the byte the VM will use to identify this integer type.
+    #[inline]
+    pub const fn get_byte_identifier(&self) -> u8 {
+        match self {
+            Self::I8 => 0,
+            Self::I16 => 1,
+            Self::I32 => 2,
+            Self::I64 => 3,
+            Self::I128 => 4,
+            Self::U8 => 5,
+            Self::U16 => 6,
+            Self::U32 => 7,
+            Self::U64 => 8,
+            Self::U128 => 9,
+        }
+    }
+
+    /// Match the integer type from the given byte.
+    ///
+    /// See [IntType::get_byte_identifier()].
+    #[inline]
+    pub const fn from_byte_identifier(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => Self::I8,
+            1 => Self::I16,
+            2 => Self::I32,
+            3 => Self::I64,
+            4 => Self::I128,
+            5 => Self::U8,
+            6 => Self::U16,
+            7 => Self::U32,
+            8 => Self::U64,
+            9 => Self::U128,
+            _ => return None,
+        })
+    }
+}
+
 /// An enum representing a bytecode instruction.
 ///
 /// For bytecode mapping, see the [byte_id] module.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Instruction {
     /// Set the execution index.
     ///
@@ -100,9 +211,115 @@ pub enum Instruction {
 
     /// Read data then load its pointer into ext_a of the execution context.
     ///
+    /// Extern calls read their arguments from ext_a, instead of popping them from the stack, until
+    /// ext_a is loaded again.
     /// This is a performance optimization to avoid repeated memory mutations and accesses during
     /// function calls.
     LoadA(ReadOperation),
+
+    /// Read both operands as the given integer type, then write `lhs + rhs` to the memory pointer
+    /// index.
+    ///
+    /// Overflow wraps around (two's complement).
+    Add(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `lhs - rhs` to the memory pointer
+    /// index.
+    ///
+    /// Overflow wraps around (two's complement).
+    Sub(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `lhs * rhs` to the memory pointer
+    /// index.
+    ///
+    /// Overflow wraps around (two's complement).
+    Mul(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `lhs / rhs` to the memory pointer
+    /// index.
+    ///
+    /// Division by zero traps. Signed overflow (`MIN / -1`) wraps around to `MIN`.
+    Div(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `lhs % rhs` to the memory pointer
+    /// index.
+    ///
+    /// Division by zero traps. Signed overflow (`MIN % -1`) wraps around to `0`.
+    Rem(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read the operand as the given integer type, then write its negation to the memory pointer
+    /// index.
+    ///
+    /// Overflow wraps around (two's complement), so negating `MIN` or any unsigned integer other
+    /// than `0` does not trap.
+    Neg(IntType, usize, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs == rhs`, otherwise `0u8`.
+    Eq(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs != rhs`, otherwise `0u8`.
+    Ne(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs < rhs`, otherwise `0u8`.
+    ///
+    /// The signedness of the comparison is decided by the [IntType].
+    Lt(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs <= rhs`, otherwise `0u8`.
+    ///
+    /// The signedness of the comparison is decided by the [IntType].
+    Le(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs > rhs`, otherwise `0u8`.
+    ///
+    /// The signedness of the comparison is decided by the [IntType].
+    Gt(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read both operands as the given integer type, then write `1u8` to the memory pointer index
+    /// if `lhs >= rhs`, otherwise `0u8`.
+    ///
+    /// The signedness of the comparison is decided by the [IntType].
+    Ge(IntType, usize, ReadOperation, ReadOperation),
+
+    /// Read the condition, then set the execution index if any of its bytes are not zero.
+    ///
+    /// See [Self::Jump].
+    JumpIf(usize, ReadOperation),
+
+    /// Read the condition, then set the execution index if all of its bytes are zero.
+    ///
+    /// See [Self::Jump].
+    JumpIfNot(usize, ReadOperation),
+
+    /// Pop the entry at the top of the stack, then write its bytes to the memory pointer index.
+    ///
+    /// Traps if the stack is empty. The entry is not popped if it cannot be written.
+    Pop(usize),
+
+    /// Pop the entry at the top of the stack, discarding it.
+    ///
+    /// Traps if the stack is empty.
+    Drop,
+
+    /// Push a copy of the entry at the top of the stack.
+    ///
+    /// Traps if the stack is empty.
+    Dup,
+
+    /// Swap the two entries at the top of the stack.
+    ///
+    /// Traps if the stack contains less than two entries.
+    Swap,
+
+    /// Push a copy of the entry below the top of the stack.
+    ///
+    /// Traps if the stack contains less than two entries.
+    Over,
 }
 
 impl Instruction {
@@ -118,6 +335,136 @@ impl Instruction {
             Self::Return => byte_id::I_RETURN,
             Self::Call(_) => byte_id::I_CALL,
             Self::LoadA(_) => byte_id::I_LOAD_A,
+            Self::Add(..) => byte_id::I_ADD,
+            Self::Sub(..) => byte_id::I_SUB,
+            Self::Mul(..) => byte_id::I_MUL,
+            Self::Div(..) => byte_id::I_DIV,
+            Self::Rem(..) => byte_id::I_REM,
+            Self::Neg(..) => byte_id::I_NEG,
+            Self::Eq(..) => byte_id::I_EQ,
+            Self::Ne(..) => byte_id::I_NE,
+            Self::Lt(..) => byte_id::I_LT,
+            Self::Le(..) => byte_id::I_LE,
+            Self::Gt(..) => byte_id::I_GT,
+            Self::Ge(..) => byte_id::I_GE,
+            Self::JumpIf(..) => byte_id::I_JUMP_IF,
+            Self::JumpIfNot(..) => byte_id::I_JUMP_IF_NOT,
+            Self::Pop(_) => byte_id::I_POP,
+            Self::Drop => byte_id::I_DROP,
+            Self::Dup => byte_id::I_DUP,
+            Self::Swap => byte_id::I_SWAP,
+            Self::Over => byte_id::I_OVER,
+        }
+    }
+
+    /// Get the amount of bytes this instruction compiles to under the given [ProgramOptions].
+    ///
+    /// The encoded length never depends on the values of memory pointer indexes, only on the
+    /// [options::MemoryPointerLength].
+    pub fn encoded_len(&self, program_options: &ProgramOptions) -> usize {
+        let span = program_options.ptr_len().get_span();
+
+        1 + match self {
+            Self::ExternCall(_) | Self::Call(_) | Self::Jump(_) | Self::Pop(_) => span,
+            Self::Push(rd) | Self::LoadA(rd) => rd.encoded_len(program_options),
+
+            Self::Mutate(_, rd) | Self::JumpIf(_, rd) | Self::JumpIfNot(_, rd) => {
+                span + rd.encoded_len(program_options)
+            }
+
+            Self::Add(_, _, lhs, rhs)
+            | Self::Sub(_, _, lhs, rhs)
+            | Self::Mul(_, _, lhs, rhs)
+            | Self::Div(_, _, lhs, rhs)
+            | Self::Rem(_, _, lhs, rhs)
+            | Self::Eq(_, _, lhs, rhs)
+            | Self::Ne(_, _, lhs, rhs)
+            | Self::Lt(_, _, lhs, rhs)
+            | Self::Le(_, _, lhs, rhs)
+            | Self::Gt(_, _, lhs, rhs)
+            | Self::Ge(_, _, lhs, rhs) => {
+                1 + span + lhs.encoded_len(program_options) + rhs.encoded_len(program_options)
+            }
+
+            Self::Neg(_, _, rd) => 1 + span + rd.encoded_len(program_options),
+            Self::Return | Self::Drop | Self::Dup | Self::Swap | Self::Over => 0,
+        }
+    }
+
+    /// Check whether every memory pointer sized value of this instruction fits the given
+    /// [MemoryPointerLength].
+    ///
+    /// Compiling an instruction that does not fit truncates the values that are too large, see
+    /// [MemoryPointerLength::fit()].
+    pub fn fits(&self, ptr_len: &MemoryPointerLength) -> bool {
+        match self {
+            Self::ExternCall(ptr) | Self::Call(ptr) | Self::Jump(ptr) | Self::Pop(ptr) => {
+                ptr_len.can_fit(*ptr)
+            }
+
+            Self::Push(rd) | Self::LoadA(rd) => rd.fits(ptr_len),
+
+            Self::Mutate(ptr, rd)
+            | Self::Neg(_, ptr, rd)
+            | Self::JumpIf(ptr, rd)
+            | Self::JumpIfNot(ptr, rd) => ptr_len.can_fit(*ptr) && rd.fits(ptr_len),
+
+            Self::Add(_, ptr, lhs, rhs)
+            | Self::Sub(_, ptr, lhs, rhs)
+            | Self::Mul(_, ptr, lhs, rhs)
+            | Self::Div(_, ptr, lhs, rhs)
+            | Self::Rem(_, ptr, lhs, rhs)
+            | Self::Eq(_, ptr, lhs, rhs)
+            | Self::Ne(_, ptr, lhs, rhs)
+            | Self::Lt(_, ptr, lhs, rhs)
+            | Self::Le(_, ptr, lhs, rhs)
+            | Self::Gt(_, ptr, lhs, rhs)
+            | Self::Ge(_, ptr, lhs, rhs) => {
+                ptr_len.can_fit(*ptr) && lhs.fits(ptr_len) && rhs.fits(ptr_len)
+            }
+
+            Self::Return | Self::Drop | Self::Dup | Self::Swap | Self::Over => true,
+        }
+    }
+
+    /// Get mutable references to every memory pointer index of this instruction, in the order
+    /// they are compiled in.
+    ///
+    /// This includes jump and call targets, destinations, and the indexes of
+    /// [ReadOperation::Point] operands. Extern call ids and read lengths are not included.
+    pub fn addresses_mut(&mut self) -> Vec<&mut usize> {
+        match self {
+            Self::Jump(ptr) | Self::Call(ptr) | Self::Pop(ptr) => vec![ptr],
+
+            Self::ExternCall(_)
+            | Self::Return
+            | Self::Drop
+            | Self::Dup
+            | Self::Swap
+            | Self::Over => Vec::new(),
+
+            Self::Push(rd) | Self::LoadA(rd) => rd.address_mut().into_iter().collect(),
+
+            Self::Mutate(ptr, rd)
+            | Self::Neg(_, ptr, rd)
+            | Self::JumpIf(ptr, rd)
+            | Self::JumpIfNot(ptr, rd) => [ptr].into_iter().chain(rd.address_mut()).collect(),
+
+            Self::Add(_, ptr, lhs, rhs)
+            | Self::Sub(_, ptr, lhs, rhs)
+            | Self::Mul(_, ptr, lhs, rhs)
+            | Self::Div(_, ptr, lhs, rhs)
+            | Self::Rem(_, ptr, lhs, rhs)
+            | Self::Eq(_, ptr, lhs, rhs)
+            | Self::Ne(_, ptr, lhs, rhs)
+            | Self::Lt(_, ptr, lhs, rhs)
+            | Self::Le(_, ptr, lhs, rhs)
+            | Self::Gt(_, ptr, lhs, rhs)
+            | Self::Ge(_, ptr, lhs, rhs) => [ptr]
+                .into_iter()
+                .chain(lhs.address_mut())
+                .chain(rhs.address_mut())
+                .collect(),
         }
     }
 }
@@ -127,7 +474,7 @@ impl Compile for Instruction {
         dest.push(self.get_identifier_byte());
 
         match self {
-            Self::ExternCall(ptr) | Self::Call(ptr) | Self::Jump(ptr) => {
+            Self::ExternCall(ptr) | Self::Call(ptr) | Self::Jump(ptr) | Self::Pop(ptr) => {
                 dest.extend(program_options.ptr_len().fit(*ptr))
             }
 
@@ -138,7 +485,35 @@ impl Compile for Instruction {
                 value.compile_into(dest, program_options);
             }
 
-            Self::Return => (),
+            Self::Add(int_type, ptr_dest, lhs, rhs)
+            | Self::Sub(int_type, ptr_dest, lhs, rhs)
+            | Self::Mul(int_type, ptr_dest, lhs, rhs)
+            | Self::Div(int_type, ptr_dest, lhs, rhs)
+            | Self::Rem(int_type, ptr_dest, lhs, rhs)
+            | Self::Eq(int_type, ptr_dest, lhs, rhs)
+            | Self::Ne(int_type, ptr_dest, lhs, rhs)
+            | Self::Lt(int_type, ptr_dest, lhs, rhs)
+            | Self::Le(int_type, ptr_dest, lhs, rhs)
+            | Self::Gt(int_type, ptr_dest, lhs, rhs)
+            | Self::Ge(int_type, ptr_dest, lhs, rhs) => {
+                dest.push(int_type.get_byte_identifier());
+                dest.extend(program_options.ptr_len().fit(*ptr_dest));
+                lhs.compile_into(dest, program_options);
+                rhs.compile_into(dest, program_options);
+            }
+
+            Self::Neg(int_type, ptr_dest, value) => {
+                dest.push(int_type.get_byte_identifier());
+                dest.extend(program_options.ptr_len().fit(*ptr_dest));
+                value.compile_into(dest, program_options);
+            }
+
+            Self::JumpIf(ptr, condition) | Self::JumpIfNot(ptr, condition) => {
+                dest.extend(program_options.ptr_len().fit(*ptr));
+                condition.compile_into(dest, program_options);
+            }
+
+            Self::Return | Self::Drop | Self::Dup | Self::Swap | Self::Over => (),
         }
     }
 }
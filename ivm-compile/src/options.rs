@@ -1,4 +1,5 @@
 use crate::Compile;
+use std::error::Error;
 use std::fmt::{Display, Formatter};
 
 /// The current compile feature version of this build.
@@ -11,7 +12,7 @@ use std::fmt::{Display, Formatter};
 ///
 /// However, this does not mean that the VM will never deprecate features and/or mark them for
 /// removal.
-pub const CCFV: u32 = 1;
+pub const CCFV: u32 = 2;
 
 pub mod header_format_doc {
     //! This module's purpose is purely for documentation.
@@ -28,7 +29,21 @@ pub mod header_format_doc {
     //! // See [MemoryPointerLength::get_byte_identifier].
     //! // **required - since CFV 1**
     //! MemoryPointerLength: MemoryPointerLength#get_byte_identifier()
+    //!
+    //! // 8 bytes: little endian u64.
+    //! // The memory pool index at which the VM begins execution.
+    //! // **required - since CFV 1**
+    //! ExecutionStart: [u8; 8],
     //! ```
+    //!
+    //! The program body directly follows the header.
+    //!
+    //! # Versions
+    //! - CFV 1: The program body is placed after 4 bytes of registers.
+    //! - CFV 2: The program body is placed after 8 bytes of registers. The header format is
+    //!   unchanged.
+    //!
+    //! See [crate::image] for writing and reading complete images.
 }
 
 /// An enum deciding the amount of bytes required to point to a location in memory.
@@ -40,7 +55,7 @@ pub mod header_format_doc {
 /// X32b => [0xFF, 0xFF, 0xFF, 0xFF]
 /// X64b => [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum MemoryPointerLength {
     /// 32 bit memory pointers - (4 bytes).
     X32b,
@@ -73,11 +88,23 @@ impl MemoryPointerLength {
     }
 
     /// Convert a memory pointer index to its little-endian byte representation.
+    ///
+    /// Indexes that do not [fit](Self::can_fit()) this length are truncated.
     #[inline]
     pub fn fit(&self, mem_ptr_index: usize) -> Vec<u8> {
         mem_ptr_index.to_le_bytes()[..self.get_span()].to_vec()
     }
 
+    /// Check whether the given memory pointer index can be represented in this length without
+    /// being truncated.
+    #[inline]
+    pub fn can_fit(&self, mem_ptr_index: usize) -> bool {
+        match self {
+            Self::X32b => u32::try_from(mem_ptr_index).is_ok(),
+            Self::X64b => u64::try_from(mem_ptr_index).is_ok(),
+        }
+    }
+
     /// Get the byte size of this memory size.
     ///
     /// `X32b => 4 bytes, X64b => 8 bytes.`
@@ -116,10 +143,11 @@ impl MemoryPointerLength {
 }
 
 /// A struct containing the required options for the VM.
-/// This struct represents an ivmc bytecode header.
+/// This struct represents the options declared in an ivmc bytecode header.
 ///
-/// See [ProgramOptions::write_bytecode(Vec)].
-#[derive(Clone, Debug)]
+/// Compiling a ProgramOptions only writes the options, not the full header. See
+/// [crate::image::write_header()].
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProgramOptions {
     cfv: u32,
     ptr_len: MemoryPointerLength,
@@ -164,6 +192,7 @@ impl Default for ProgramOptions {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum InvalidHeaderCause {
     /// The header format was not fulfilled.
     ///
@@ -174,6 +203,11 @@ pub enum InvalidHeaderCause {
     ///
     /// For example, a CFV is too large.
     UnrecognizedValue,
+
+    /// The value was recognized, but is not supported.
+    ///
+    /// For example, the CFV expects a memory layout the VM no longer provides.
+    Unsupported,
 }
 
 impl InvalidHeaderCause {
@@ -188,6 +222,10 @@ impl InvalidHeaderCause {
                 "this bytecode input may have been compiled by a later version of ivmc",
                 DOC_HELP,
             ],
+            Self::Unsupported => &[
+                "this bytecode input must be recompiled by the current version of ivmc",
+                DOC_HELP,
+            ],
         }
     }
 }
@@ -200,6 +238,7 @@ impl Display for InvalidHeaderCause {
             match self {
                 Self::UnrecognizedValue => "an unrecognized value was encountered",
                 Self::FormatNotFulfilled => "the header format was not fulfilled",
+                Self::Unsupported => "an unsupported value was encountered",
             }
         )
     }
@@ -207,6 +246,7 @@ impl Display for InvalidHeaderCause {
 
 /// An error returned when the header of a bytecode input did not meet the official ivmc bytecode
 /// header format.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InvalidHeaderError {
     cause: InvalidHeaderCause,
     message: String,
@@ -241,3 +281,5 @@ impl Display for InvalidHeaderError {
         write!(f, "{}: {}", self.cause, self.message)
     }
 }
+
+impl Error for InvalidHeaderError {}
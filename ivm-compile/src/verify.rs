@@ -0,0 +1,247 @@
+//! A module for statically verifying ivm bytecode before it is executed.
+//!
+//! Verification decodes the bytecode from start to end, so it must contain instructions only. Data
+//! should be placed after the verified range, or in a separate part of the memory pool.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use crate::decode::{Decode, DecodeErrorCause};
+use crate::options::ProgramOptions;
+use crate::{Instruction, ReadOperation};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The instruction could not be decoded, for example due to an unrecognized opcode or a
+    /// [ReadOperation::Local] running past the end of the bytecode.
+    ///
+    /// Verification stops at the first malformed instruction.
+    Malformed(DecodeErrorCause),
+
+    /// A jump or call target does not land on an instruction boundary, or the end of the code.
+    InvalidJumpTarget(usize),
+
+    /// A memory range read or written by the instruction does not fit the memory.
+    OutOfBounds { index: usize, len: usize },
+
+    /// An operand length does not match the size of the integer type of the instruction.
+    OperandSizeMismatch { expected: usize, found: usize },
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(cause) => write!(f, "malformed instruction: {cause}"),
+            Self::InvalidJumpTarget(target) => write!(f, "invalid jump target {target}"),
+            Self::OutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "memory range of {len} bytes at index {index} is out of bounds"
+                )
+            }
+            Self::OperandSizeMismatch { expected, found } => {
+                write!(f, "operand of {found} bytes, expected {expected} bytes")
+            }
+        }
+    }
+}
+
+/// A problem found by [verify()].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    offset: usize,
+    kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    /// Get the offset of the instruction within the bytecode.
+    ///
+    /// For [DiagnosticKind::Malformed], this is the offset at which decoding failed.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the kind of this diagnostic.
+    #[inline]
+    pub const fn kind(&self) -> &DiagnosticKind {
+        &self.kind
+    }
+
+    #[inline]
+    pub const fn new(offset: usize, kind: DiagnosticKind) -> Self {
+        Self { offset, kind }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+fn read_op_len(read_op: &ReadOperation) -> usize {
+    match read_op {
+        ReadOperation::Local(bytes) => bytes.len(),
+        ReadOperation::Point(len, _) => *len,
+    }
+}
+
+/// Collects the diagnostics of a single instruction.
+struct Checker {
+    memory_len: usize,
+    kinds: Vec<DiagnosticKind>,
+}
+
+impl Checker {
+    fn range(&mut self, index: usize, len: usize) {
+        if index
+            .checked_add(len)
+            .is_none_or(|end| end > self.memory_len)
+        {
+            self.kinds.push(DiagnosticKind::OutOfBounds { index, len });
+        }
+    }
+
+    fn read_op(&mut self, read_op: &ReadOperation) {
+        if let ReadOperation::Point(len, index) = read_op {
+            self.range(*index, *len);
+        }
+    }
+
+    fn operand(&mut self, expected: usize, read_op: &ReadOperation) {
+        self.read_op(read_op);
+
+        let found = read_op_len(read_op);
+
+        if found != expected {
+            self.kinds
+                .push(DiagnosticKind::OperandSizeMismatch { expected, found });
+        }
+    }
+}
+
+/// Verify the given bytecode.
+///
+/// The base is the memory pool index the bytecode will be placed at, and the memory length is the
+/// largest size the memory pool may have. Jump and call targets, as well as memory ranges, are
+/// absolute memory pool indexes.
+///
+/// Returns every diagnostic, ordered by offset. The bytecode is valid if the result is empty.
+pub fn verify(
+    bytes: &[u8],
+    program_options: &ProgramOptions,
+    base: usize,
+    memory_len: usize,
+) -> Vec<Diagnostic> {
+    let mut instructions = Vec::new();
+    let mut malformed = None;
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        match Instruction::decode(bytes, offset, program_options) {
+            Ok((instruction, read)) => {
+                instructions.push((offset, instruction));
+                offset += read;
+            }
+            Err(err) => {
+                malformed = Some(Diagnostic::new(
+                    err.offset(),
+                    DiagnosticKind::Malformed(err.cause().clone()),
+                ));
+                break;
+            }
+        }
+    }
+
+    // Targets may point forward, so every boundary must be known before checking any jump.
+    let mut boundaries = instructions
+        .iter()
+        .map(|(offset, _)| base + offset)
+        .collect::<HashSet<_>>();
+
+    if malformed.is_none() {
+        boundaries.insert(base + bytes.len());
+    }
+
+    let mut diagnostics = Vec::new();
+
+    for (offset, instruction) in &instructions {
+        let mut checker = Checker {
+            memory_len,
+            kinds: Vec::new(),
+        };
+
+        match instruction {
+            Instruction::Jump(target) | Instruction::Call(target) => {
+                if !boundaries.contains(target) {
+                    checker
+                        .kinds
+                        .push(DiagnosticKind::InvalidJumpTarget(*target));
+                }
+            }
+
+            Instruction::JumpIf(target, rd) | Instruction::JumpIfNot(target, rd) => {
+                if !boundaries.contains(target) {
+                    checker
+                        .kinds
+                        .push(DiagnosticKind::InvalidJumpTarget(*target));
+                }
+                checker.read_op(rd);
+            }
+
+            Instruction::Push(rd) | Instruction::LoadA(rd) => checker.read_op(rd),
+
+            Instruction::Mutate(dest, rd) => {
+                checker.range(*dest, read_op_len(rd));
+                checker.read_op(rd);
+            }
+
+            Instruction::Add(int_type, dest, lhs, rhs)
+            | Instruction::Sub(int_type, dest, lhs, rhs)
+            | Instruction::Mul(int_type, dest, lhs, rhs)
+            | Instruction::Div(int_type, dest, lhs, rhs)
+            | Instruction::Rem(int_type, dest, lhs, rhs) => {
+                checker.range(*dest, int_type.get_size());
+                checker.operand(int_type.get_size(), lhs);
+                checker.operand(int_type.get_size(), rhs);
+            }
+
+            Instruction::Eq(int_type, dest, lhs, rhs)
+            | Instruction::Ne(int_type, dest, lhs, rhs)
+            | Instruction::Lt(int_type, dest, lhs, rhs)
+            | Instruction::Le(int_type, dest, lhs, rhs)
+            | Instruction::Gt(int_type, dest, lhs, rhs)
+            | Instruction::Ge(int_type, dest, lhs, rhs) => {
+                checker.range(*dest, 1);
+                checker.operand(int_type.get_size(), lhs);
+                checker.operand(int_type.get_size(), rhs);
+            }
+
+            Instruction::Neg(int_type, dest, rd) => {
+                checker.range(*dest, int_type.get_size());
+                checker.operand(int_type.get_size(), rd);
+            }
+
+            // The length of the popped entry is only known at runtime.
+            Instruction::Pop(dest) => checker.range(*dest, 0),
+
+            Instruction::ExternCall(_)
+            | Instruction::Return
+            | Instruction::Drop
+            | Instruction::Dup
+            | Instruction::Swap
+            | Instruction::Over => (),
+        }
+
+        diagnostics.extend(
+            checker
+                .kinds
+                .into_iter()
+                .map(|kind| Diagnostic::new(*offset, kind)),
+        );
+    }
+
+    diagnostics.extend(malformed);
+    diagnostics
+}
@@ -38,7 +38,7 @@ impl Adapt {
 #[inline]
 pub fn get_header_size(cfv: u32) -> Option<usize> {
     match cfv {
-        options::CCFV => Some(CCFV_HEADER_LEN),
+        1..=options::CCFV => Some(CFV1_HEADER_LEN),
         _ => None,
     }
 }
@@ -46,6 +46,8 @@ pub fn get_header_size(cfv: u32) -> Option<usize> {
 pub type AdapterResult = Result<Adapt, InvalidHeaderError>;
 
 /// Try to retrieve an [Adapt] using the bytecode header format defined for CFV 1.
+///
+/// Every later compile feature version uses the same header format.
 pub fn try_retrieve_cfv1(bytes: &[u8]) -> AdapterResult {
     if bytes.len() < CFV1_HEADER_LEN {
         return Err(InvalidHeaderError::from(
@@ -83,7 +85,22 @@ pub fn try_retrieve_cfv1(bytes: &[u8]) -> AdapterResult {
 ///
 /// See the [crate::options::header_format_doc] module for full documentation regarding the official
 /// bytecode header.
-#[inline(always)]
 pub fn get_program_options(bytes: &[u8]) -> AdapterResult {
-    try_retrieve_cfv1(bytes)
+    let cfv = match bytes.get(..4) {
+        Some(cfv) => u32::from_le_bytes(cfv.try_into().unwrap()),
+        None => {
+            return Err(InvalidHeaderError::from(
+                InvalidHeaderCause::FormatNotFulfilled,
+                "header input too short",
+            ))
+        }
+    };
+
+    match cfv {
+        1..=options::CCFV => try_retrieve_cfv1(bytes),
+        _ => Err(InvalidHeaderError::new(
+            InvalidHeaderCause::UnrecognizedValue,
+            format!("unrecognized compile feature version {cfv}"),
+        )),
+    }
 }
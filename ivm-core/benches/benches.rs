@@ -24,12 +24,12 @@ fn hello_world() {
         Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_WRITE),
     ]);
 
-    let mut extern_map = IvmX32ExternMap;
+    let mut extern_map = IvmX32ExternMap::new();
     let mut env = ExecutionEnvironment::new(&mut extern_map);
 
     let start = Instant::now();
 
-    vm.continue_execution(&mut env);
+    vm.continue_execution(&mut env).unwrap();
     println!("{:?}", start.elapsed());
 }
 
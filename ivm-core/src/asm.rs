@@ -0,0 +1,694 @@
+//! An assembler for the textual ivm assembly language (`.ivmasm`).
+//!
+//! # Syntax
+//!
+//! Every line holds at most one statement, optionally preceded by a label definition. Comments
+//! start with `;` and run until the end of the line.
+//!
+//! ```text
+//! ; count down from 5
+//!     mutate counter, local(i32 5)
+//! loop:
+//!     sub i32 counter, point(4, counter), local(i32 1)
+//!     jump_if loop, point(4, counter)
+//!     load_a point(5, done)
+//!     extern_call 0
+//!     return
+//!
+//! counter: .zero 4
+//! done:    .bytes "done", 0x0a
+//! ```
+//!
+//! Instructions are written as their [mnemonic](crate::fmt::mnemonic), followed by their
+//! comma-separated operands in the order of the [Instruction] variant. Integer types (`i8` to
+//! `u128`) are written directly after the mnemonic, without a comma.
+//!
+//! Memory pointer indexes may be written as integers or as label names. A label resolves to the
+//! memory pool index of the statement following its definition.
+//!
+//! Read operations are written as `local(<data>)` and `point(<length>, <index>)`.
+//!
+//! Data is a comma-separated list of items, each being one of:
+//! - a string literal, such as `"Hello\n"`, supporting the `\n`, `\r`, `\t`, `\0`, `\\`, `\"`
+//!   and `\xNN` escapes
+//! - a single byte, such as `42` or `0x2a`
+//! - a typed integer, such as `i32 -5`, written in little endian byte order
+//!
+//! The following directives emit raw data:
+//! - `.bytes <data>`
+//! - `.zero <length>`
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use ivm_compile::builder::{BuildErrorCause, BuiltProgram, ProgramBuilder};
+use ivm_compile::options::ProgramOptions;
+use ivm_compile::{Instruction, IntType, ReadOperation};
+
+/// An error returned when assembly source could not be assembled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl AsmError {
+    /// Get the line at which the error occurred, starting at 1.
+    ///
+    /// This is 0 if the error has no position in the source.
+    #[inline]
+    pub const fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the column at which the error occurred, starting at 1.
+    ///
+    /// This is 0 if the error has no position in the source.
+    #[inline]
+    pub const fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Get the message of this error.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub const fn new(line: usize, column: usize, message: String) -> Self {
+        Self {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Ident(String),
+    Int { negative: bool, magnitude: u128 },
+    Str(Vec<u8>),
+    Comma,
+    Colon,
+    LParen,
+    RParen,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "'{name}'"),
+            Self::Int { .. } => write!(f, "integer"),
+            Self::Str(_) => write!(f, "string literal"),
+            Self::Comma => write!(f, "','"),
+            Self::Colon => write!(f, "':'"),
+            Self::LParen => write!(f, "'('"),
+            Self::RParen => write!(f, "')'"),
+        }
+    }
+}
+
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn parse_int(text: &str) -> Option<TokenKind> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let text = text.replace('_', "");
+
+    let magnitude = if let Some(hex) = text.strip_prefix("0x") {
+        u128::from_str_radix(hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u128::from_str_radix(bin, 2)
+    } else {
+        text.parse()
+    };
+
+    Some(TokenKind::Int {
+        negative,
+        magnitude: magnitude.ok()?,
+    })
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AsmError> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let column = i + 1;
+        let err = |message: String| AsmError::new(line, column, message);
+
+        let kind = match chars[i] {
+            ';' => break,
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+
+            '"' => {
+                let mut bytes = Vec::new();
+
+                loop {
+                    i += 1;
+
+                    match chars.get(i) {
+                        None => return Err(err("unterminated string literal".to_string())),
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escape_column = i + 1;
+                            let escape_err = || {
+                                AsmError::new(
+                                    line,
+                                    escape_column,
+                                    "invalid escape sequence".to_string(),
+                                )
+                            };
+
+                            i += 1;
+
+                            bytes.push(match chars.get(i).ok_or_else(escape_err)? {
+                                'n' => b'\n',
+                                'r' => b'\r',
+                                't' => b'\t',
+                                '0' => 0,
+                                '\\' => b'\\',
+                                '"' => b'"',
+                                'x' => {
+                                    let hex = chars
+                                        .get(i + 1..i + 3)
+                                        .ok_or_else(escape_err)?
+                                        .iter()
+                                        .collect::<String>();
+
+                                    i += 2;
+                                    u8::from_str_radix(&hex, 16).map_err(|_| escape_err())?
+                                }
+                                _ => return Err(escape_err()),
+                            });
+                        }
+                        Some(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                TokenKind::Str(bytes)
+            }
+
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = i;
+
+                while chars
+                    .get(i + 1)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    i += 1;
+                }
+
+                let text = chars[start..=i].iter().collect::<String>();
+                parse_int(&text).ok_or_else(|| err(format!("invalid integer '{text}'")))?
+            }
+
+            c if c.is_alphabetic() || c == '_' || c == '.' => {
+                let start = i;
+
+                while chars
+                    .get(i + 1)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    i += 1;
+                }
+                TokenKind::Ident(chars[start..=i].iter().collect())
+            }
+
+            c => return Err(err(format!("unexpected character '{c}'"))),
+        };
+
+        tokens.push(Token { kind, column });
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+fn parse_int_type(name: &str) -> Option<IntType> {
+    Some(match name {
+        "i8" => IntType::I8,
+        "i16" => IntType::I16,
+        "i32" => IntType::I32,
+        "i64" => IntType::I64,
+        "i128" => IntType::I128,
+        "u8" => IntType::U8,
+        "u16" => IntType::U16,
+        "u32" => IntType::U32,
+        "u64" => IntType::U64,
+        "u128" => IntType::U128,
+        _ => return None,
+    })
+}
+
+/// Get the little endian bytes of the integer, if it fits the integer type.
+fn int_bytes(int_type: IntType, negative: bool, magnitude: u128) -> Option<Vec<u8>> {
+    let size = int_type.get_size();
+    let bits = size as u32 * 8;
+
+    let fits = match (int_type.is_signed(), negative) {
+        (true, true) => magnitude <= 1 << (bits - 1),
+        (true, false) => magnitude < 1 << (bits - 1),
+        (false, true) => magnitude == 0,
+        (false, false) => bits == 128 || magnitude >> bits == 0,
+    };
+
+    let value = if negative {
+        magnitude.wrapping_neg()
+    } else {
+        magnitude
+    };
+
+    fits.then(|| value.to_le_bytes()[..size].to_vec())
+}
+
+/// A reference to a label, resolved by the [ProgramBuilder].
+///
+/// The slot is the index of the memory pointer index in [Instruction::addresses_mut()].
+struct LabelRef {
+    slot: usize,
+    name: String,
+    line: usize,
+    column: usize,
+}
+
+enum Statement {
+    Instruction(Instruction, Vec<LabelRef>),
+    Data(Vec<u8>),
+}
+
+struct LineParser<'a> {
+    line: usize,
+    tokens: &'a [Token],
+    pos: usize,
+    end_column: usize,
+    slots: usize,
+    label_refs: Vec<LabelRef>,
+}
+
+impl<'a> LineParser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn column(&self) -> usize {
+        self.peek().map_or(self.end_column, |token| token.column)
+    }
+
+    fn error(&self, column: usize, message: String) -> AsmError {
+        AsmError::new(self.line, column, message)
+    }
+
+    fn unexpected(&self, expected: &str) -> AsmError {
+        let found = match self.peek() {
+            Some(token) => token.kind.to_string(),
+            None => "end of line".to_string(),
+        };
+        self.error(self.column(), format!("expected {expected}, found {found}"))
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        let matches = self.peek().is_some_and(|token| &token.kind == kind);
+        self.pos += matches as usize;
+        matches
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), AsmError> {
+        if self.eat(&kind) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&kind.to_string()))
+        }
+    }
+
+    fn integer(&mut self) -> Result<(bool, u128, usize), AsmError> {
+        match self.peek() {
+            Some(Token {
+                kind:
+                    TokenKind::Int {
+                        negative,
+                        magnitude,
+                    },
+                column,
+            }) => {
+                self.pos += 1;
+                Ok((*negative, *magnitude, *column))
+            }
+            _ => Err(self.unexpected("integer")),
+        }
+    }
+
+    fn usize_value(&mut self) -> Result<usize, AsmError> {
+        let (negative, magnitude, column) = self.integer()?;
+
+        usize::try_from(magnitude)
+            .ok()
+            .filter(|_| !negative)
+            .ok_or_else(|| self.error(column, "integer out of range".to_string()))
+    }
+
+    fn address(&mut self) -> Result<usize, AsmError> {
+        let slot = self.slots;
+        self.slots += 1;
+
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                column,
+            }) => {
+                self.pos += 1;
+                self.label_refs.push(LabelRef {
+                    slot,
+                    name: name.clone(),
+                    line: self.line,
+                    column: *column,
+                });
+                Ok(0)
+            }
+            Some(Token {
+                kind: TokenKind::Int { .. },
+                ..
+            }) => self.usize_value(),
+            _ => Err(self.unexpected("address or label")),
+        }
+    }
+
+    fn int_type(&mut self) -> Result<IntType, AsmError> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                ..
+            }) if parse_int_type(name).is_some() => {
+                self.pos += 1;
+                Ok(parse_int_type(name).unwrap())
+            }
+            _ => Err(self.unexpected("integer type")),
+        }
+    }
+
+    fn data_item(&mut self, dest: &mut Vec<u8>) -> Result<(), AsmError> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected("data"));
+        };
+
+        match &token.kind {
+            TokenKind::Str(bytes) => {
+                self.pos += 1;
+                dest.extend_from_slice(bytes);
+            }
+            TokenKind::Int { .. } => {
+                let (negative, magnitude, column) = self.integer()?;
+
+                dest.push(
+                    u8::try_from(magnitude)
+                        .ok()
+                        .filter(|_| !negative)
+                        .ok_or_else(|| {
+                            self.error(
+                                column,
+                                "byte out of range, use a typed integer instead".to_string(),
+                            )
+                        })?,
+                );
+            }
+            TokenKind::Ident(name) if parse_int_type(name).is_some() => {
+                let int_type = self.int_type()?;
+                let (negative, magnitude, column) = self.integer()?;
+
+                dest.extend(int_bytes(int_type, negative, magnitude).ok_or_else(|| {
+                    self.error(column, format!("integer out of range for type {name}"))
+                })?);
+            }
+            _ => return Err(self.unexpected("data")),
+        }
+        Ok(())
+    }
+
+    fn data(&mut self) -> Result<Vec<u8>, AsmError> {
+        let mut bytes = Vec::new();
+
+        loop {
+            self.data_item(&mut bytes)?;
+
+            if !self.eat(&TokenKind::Comma) {
+                return Ok(bytes);
+            }
+        }
+    }
+
+    fn read_op(&mut self) -> Result<ReadOperation, AsmError> {
+        let name = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                ..
+            }) if name == "local" || name == "point" => name,
+            _ => return Err(self.unexpected("read operation")),
+        };
+
+        self.pos += 1;
+        self.expect(TokenKind::LParen)?;
+
+        let read_op = if name == "local" {
+            if self
+                .peek()
+                .is_some_and(|token| token.kind == TokenKind::RParen)
+            {
+                ReadOperation::Local(Vec::new())
+            } else {
+                ReadOperation::Local(self.data()?)
+            }
+        } else {
+            let len = self.usize_value()?;
+            self.expect(TokenKind::Comma)?;
+            ReadOperation::Point(len, self.address()?)
+        };
+
+        self.expect(TokenKind::RParen)?;
+        Ok(read_op)
+    }
+
+    fn instruction(&mut self, mnemonic: &str, column: usize) -> Result<Instruction, AsmError> {
+        macro_rules! binary {
+            ($variant:ident) => {{
+                let int_type = self.int_type()?;
+                let dest = self.address()?;
+                self.expect(TokenKind::Comma)?;
+                let lhs = self.read_op()?;
+                self.expect(TokenKind::Comma)?;
+                Instruction::$variant(int_type, dest, lhs, self.read_op()?)
+            }};
+        }
+
+        macro_rules! with_address {
+            ($variant:ident) => {{
+                let dest = self.address()?;
+                self.expect(TokenKind::Comma)?;
+                Instruction::$variant(dest, self.read_op()?)
+            }};
+        }
+
+        Ok(match mnemonic {
+            "jump" => Instruction::Jump(self.address()?),
+            "push" => Instruction::Push(self.read_op()?),
+            "mutate" => with_address!(Mutate),
+            "call" => Instruction::Call(self.address()?),
+            "extern_call" => Instruction::ExternCall(self.usize_value()?),
+            "return" => Instruction::Return,
+            "load_a" => Instruction::LoadA(self.read_op()?),
+            "add" => binary!(Add),
+            "sub" => binary!(Sub),
+            "mul" => binary!(Mul),
+            "div" => binary!(Div),
+            "rem" => binary!(Rem),
+            "neg" => {
+                let int_type = self.int_type()?;
+                let dest = self.address()?;
+                self.expect(TokenKind::Comma)?;
+                Instruction::Neg(int_type, dest, self.read_op()?)
+            }
+            "eq" => binary!(Eq),
+            "ne" => binary!(Ne),
+            "lt" => binary!(Lt),
+            "le" => binary!(Le),
+            "gt" => binary!(Gt),
+            "ge" => binary!(Ge),
+            "jump_if" => with_address!(JumpIf),
+            "jump_if_not" => with_address!(JumpIfNot),
+            "pop" => Instruction::Pop(self.address()?),
+            "drop" => Instruction::Drop,
+            "dup" => Instruction::Dup,
+            "swap" => Instruction::Swap,
+            "over" => Instruction::Over,
+            _ => return Err(self.error(column, format!("unknown instruction '{mnemonic}'"))),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Option<Statement>, AsmError> {
+        let (name, column) = match self.peek() {
+            None => return Ok(None),
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                column,
+            }) => (name, *column),
+            _ => return Err(self.unexpected("instruction or directive")),
+        };
+        self.pos += 1;
+
+        let statement = match name.as_str() {
+            ".bytes" => Statement::Data(self.data()?),
+            ".zero" => Statement::Data(vec![0; self.usize_value()?]),
+            _ if name.starts_with('.') => {
+                return Err(self.error(column, format!("unknown directive '{name}'")))
+            }
+            _ => {
+                let instruction = self.instruction(name, column)?;
+                Statement::Instruction(instruction, std::mem::take(&mut self.label_refs))
+            }
+        };
+
+        if self.peek().is_some() {
+            return Err(self.unexpected("end of line"));
+        }
+        Ok(Some(statement))
+    }
+}
+
+/// Assemble the given source into bytecode.
+///
+/// The base is the memory pool index the bytecode will be placed at, which labels resolve
+/// relative to. For programs introduced into a VM created by
+/// [ivm_vm::VmInstance::reserve_ivm_ext_x32()], this is [ivm_vm::ivm_ext_x32::REGISTER_RESERVED].
+///
+/// See the [module documentation](self) for the syntax.
+///
+/// # Example
+///
+/// ```
+/// use ivm_compile::options::ProgramOptions;
+/// use ivm_core::asm;
+/// use ivm_vm::ivm_ext_x32::{IvmX32ExternMap, REGISTER_RESERVED};
+/// use ivm_vm::{ExecutionEnvironment, VmInstance};
+///
+/// let source = r#"
+///     load_a point(14, message)
+///     extern_call 0 ; stdout write
+///     return
+///
+/// message: .bytes "Hello, world!\n"
+/// "#;
+///
+/// let options = ProgramOptions::default();
+/// let program = asm::assemble(source, &options, REGISTER_RESERVED).unwrap();
+///
+/// let mut vm = VmInstance::reserve_ivm_ext_x32(options);
+/// vm.introduce(program.into_bytecode());
+///
+/// let mut extern_map = IvmX32ExternMap::new();
+/// let mut env = ExecutionEnvironment::new(&mut extern_map);
+/// vm.continue_execution(&mut env).unwrap();
+/// ```
+pub fn assemble(
+    source: &str,
+    program_options: &ProgramOptions,
+    base: usize,
+) -> Result<BuiltProgram, AsmError> {
+    let mut builder = ProgramBuilder::new(base);
+    let mut label_defs = Vec::new();
+    let mut entry_refs = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(line, text)?;
+
+        let mut parser = LineParser {
+            line,
+            tokens: &tokens,
+            pos: 0,
+            end_column: text.chars().count() + 1,
+            slots: 0,
+            label_refs: Vec::new(),
+        };
+
+        if let [Token {
+            kind: TokenKind::Ident(name),
+            column,
+        }, Token {
+            kind: TokenKind::Colon,
+            ..
+        }, ..] = tokens.as_slice()
+        {
+            if name.starts_with('.') {
+                return Err(AsmError::new(
+                    line,
+                    *column,
+                    format!("invalid label '{name}'"),
+                ));
+            }
+
+            builder.label(name.clone());
+            label_defs.push((name.clone(), line, *column));
+            parser.pos = 2;
+        }
+
+        match parser.statement()? {
+            Some(Statement::Data(bytes)) => {
+                builder.data(bytes);
+                entry_refs.push(Vec::new());
+            }
+            Some(Statement::Instruction(instruction, label_refs)) => {
+                builder.instruction_with_refs(
+                    instruction,
+                    label_refs
+                        .iter()
+                        .map(|label_ref| (label_ref.slot, label_ref.name.clone())),
+                );
+                entry_refs.push(label_refs);
+            }
+            None => (),
+        }
+    }
+
+    builder.build(program_options).map_err(|err| {
+        let (line, column) = match err.cause() {
+            BuildErrorCause::UndefinedLabel(name) => entry_refs[err.entry()]
+                .iter()
+                .find(|label_ref| &label_ref.name == name)
+                .map(|label_ref| (label_ref.line, label_ref.column)),
+            BuildErrorCause::DuplicateLabel(name) => label_defs
+                .iter()
+                .filter(|(def, _, _)| def == name)
+                .nth(1)
+                .map(|(_, line, column)| (*line, *column)),
+            BuildErrorCause::PointerOverflow => entry_refs[err.entry()]
+                .first()
+                .map(|label_ref| (label_ref.line, label_ref.column)),
+            BuildErrorCause::InvalidSlot(_) => None,
+        }
+        // Errors without a position in the source are reported at line and column 0.
+        .unwrap_or((0, 0));
+
+        AsmError::new(line, column, err.cause().to_string())
+    })
+}
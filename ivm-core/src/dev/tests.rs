@@ -1,7 +1,24 @@
-use ivm_compile::options::ProgramOptions;
-use ivm_compile::{Instruction, ReadOperation};
-use ivm_vm::ivm_ext_x32::IvmX32ExternMap;
-use ivm_vm::{ivm_ext_x32, ExecutionEnvironment, VmInstance};
+use ivm_compile::builder::{BuildErrorCause, ProgramBuilder};
+use ivm_compile::decode::{DecodeError, DecodeErrorCause};
+use ivm_compile::image::ProgramImage;
+use ivm_compile::options::{InvalidHeaderCause, MemoryPointerLength, ProgramOptions};
+use ivm_compile::verify::{self, Diagnostic, DiagnosticKind};
+use ivm_compile::version_adapters;
+use ivm_compile::{Compile, Instruction, IntType, ReadOperation};
+use ivm_vm::fs;
+use ivm_vm::heap::Heap;
+use ivm_vm::ivm_ext_x32::{BufferedX32ExternMap, IvmX32ExternMap};
+use ivm_vm::registry::{ExternRegistry, RegistryError};
+use ivm_vm::security::{CallQuota, Denial, GuardedExternMap, IllegalOperationHandleMethod};
+use ivm_vm::snapshot::SnapshotError;
+use ivm_vm::trace::WriteTracer;
+use ivm_vm::trap::TrapCause;
+use ivm_vm::{
+    ivm_ext_x32, EmptyExternMap, ExecutionContext, ExecutionEnvironment, ExecutionStatus,
+    ExitStatus, MemoryPolicy, VmInstance,
+};
+
+use crate::{asm, fmt};
 
 pub fn vm_ivm_ext_x32<I>(instructions: I) -> VmInstance
 where
@@ -15,6 +32,87 @@ where
     vm
 }
 
+/// Get the memory pool index right after the given program, which tests may use as scratch memory.
+pub fn scratch_offset<I>(instructions: I) -> usize
+where
+    I: IntoIterator<Item = Instruction>,
+{
+    ivm_ext_x32::REGISTER_RESERVED
+        + ivm_compile::compile_all(instructions, &ProgramOptions::default()).len()
+}
+
+/// Concatenate the given [u32] arguments into a local read operation.
+pub fn pack_args(values: &[u32]) -> ReadOperation {
+    ReadOperation::Local(
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect(),
+    )
+}
+
+/// Push each of the given [u32] arguments as a separate stack entry.
+pub fn push_args(values: &[u32]) -> Vec<Instruction> {
+    values
+        .iter()
+        .map(|value| Instruction::Push(pack_args(&[*value])))
+        .collect()
+}
+
+/// Encode an [i32] as a local read operation.
+pub fn local_i32(value: i32) -> ReadOperation {
+    ReadOperation::Local(value.to_le_bytes().to_vec())
+}
+
+/// Load the given read operation into ext_a, then make the extern call.
+pub fn extern_call(rd: ReadOperation, call_id: usize) -> [Instruction; 2] {
+    [Instruction::LoadA(rd), Instruction::ExternCall(call_id)]
+}
+
+/// Make each extern call with its arguments loaded into ext_a, then return.
+pub fn extern_program<I>(calls: I) -> Vec<Instruction>
+where
+    I: IntoIterator<Item = (ReadOperation, usize)>,
+{
+    calls
+        .into_iter()
+        .flat_map(|(rd, call_id)| extern_call(rd, call_id))
+        .chain([Instruction::Return])
+        .collect()
+}
+
+/// Create a VM running the program built for the index of its scratch memory, followed by `len`
+/// bytes of scratch memory.
+///
+/// The program must have the same length for every index. Returns the VM and the scratch index.
+pub fn vm_with_scratch<F, I>(program: F, len: usize) -> (VmInstance, usize)
+where
+    F: Fn(u32) -> I,
+    I: IntoIterator<Item = Instruction>,
+{
+    let buf = scratch_offset(program(0));
+
+    let mut vm = vm_ivm_ext_x32(program(buf as u32));
+    vm.mem_pool.resize(buf + len, 0);
+    (vm, buf)
+}
+
+/// Read the [i32] register at the given index.
+pub fn read_register(vm: &VmInstance, index: usize) -> i32 {
+    i32::from_le_bytes(vm.mem_pool[index..index + 4].try_into().unwrap())
+}
+
+/// Execute the given amount of instructions, then read the return and error registers.
+pub fn run_extern(vm: &mut VmInstance, env: &mut ExecutionEnvironment, steps: usize) -> (i32, i32) {
+    for _ in 0..steps {
+        vm.step(env).unwrap().unwrap();
+    }
+    (
+        read_register(vm, ivm_ext_x32::REG_RETURN),
+        read_register(vm, ivm_ext_x32::REG_ERROR),
+    )
+}
+
 #[test]
 fn hello_world() {
     let mut vm = vm_ivm_ext_x32([
@@ -23,15 +121,1530 @@ fn hello_world() {
         Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_FLUSH),
     ]);
 
-    let mut extern_map = IvmX32ExternMap;
+    let mut extern_map = BufferedX32ExternMap::buffered([]);
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(extern_map.stdout, b"Hello, world!\n");
+}
+
+#[test]
+fn integer_arithmetic() {
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::Add(IntType::I32, 0, local_i32(i32::MAX), local_i32(1)),
+        Instruction::Neg(IntType::I32, 0, ReadOperation::Point(4, 0)),
+        Instruction::Div(IntType::I32, 0, ReadOperation::Point(4, 0), local_i32(-2)),
+    ]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[..4], (i32::MIN / -2).to_le_bytes());
+}
+
+#[test]
+fn conditional_loop() {
+    let init = Instruction::Mutate(0, local_i32(5));
+    let loop_start =
+        ivm_ext_x32::REGISTER_RESERVED + init.compile(&ProgramOptions::default()).len();
+
+    let mut vm = vm_ivm_ext_x32([
+        init,
+        Instruction::Sub(IntType::I32, 0, ReadOperation::Point(4, 0), local_i32(1)),
+        Instruction::JumpIf(loop_start, ReadOperation::Point(4, 0)),
+        Instruction::Lt(IntType::I32, 0, local_i32(-1), local_i32(1)),
+        Instruction::Lt(IntType::U32, 1, local_i32(-1), local_i32(1)),
+    ]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[..4], [1, 0, 0, 0]);
+}
+
+#[test]
+fn trap_reports_failing_instruction() {
+    let local_u8 = |v: u8| ReadOperation::Local(vec![v]);
+
+    let push = Instruction::Push(local_u8(1));
+    let div_index = ivm_ext_x32::REGISTER_RESERVED + push.compile(&ProgramOptions::default()).len();
+
+    let mut vm = vm_ivm_ext_x32([
+        push,
+        Instruction::Div(IntType::U8, 0, local_u8(1), local_u8(0)),
+    ]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.execution_index(), div_index);
+    assert_eq!(err.opcode(), ivm_compile::byte_id::I_DIV);
+    assert_eq!(err.cause(), &TrapCause::DivisionByZero);
+    assert_eq!(vm.execution_index, div_index);
+
+    vm.introduce([0xFF]);
+    vm.execution_index = vm.mem_pool.len() - 1;
+
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.cause(), &TrapCause::UnrecognizedInstruction);
+}
+
+#[test]
+fn memory_access_bounds() {
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let mut vm = vm_ivm_ext_x32([Instruction::Push(ReadOperation::Point(
+        8,
+        u32::MAX as usize - 4,
+    ))]);
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(
+        err.cause(),
+        &TrapCause::OutOfBounds {
+            index: u32::MAX as usize - 4,
+            len: 8
+        }
+    );
+
+    let write = || Instruction::Mutate(64, ReadOperation::Local(vec![1, 2]));
+
+    let mut vm = vm_ivm_ext_x32([write()]);
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.cause(), &TrapCause::OutOfBounds { index: 64, len: 2 });
+
+    let mut vm = vm_ivm_ext_x32([write(), Instruction::Return]);
+    vm.memory_policy = MemoryPolicy::Grow(128);
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool.len(), 66);
+    assert_eq!(vm.mem_pool[64..], [1, 2]);
+}
+
+#[test]
+fn stack_survives_introduce() {
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::Push(ReadOperation::Local(b"abc".to_vec())),
+        Instruction::Push(ReadOperation::Point(4, 0)),
+        Instruction::LoadA(ReadOperation::Local(b"ext".to_vec())),
+        Instruction::Return,
+    ]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    vm.mem_pool[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+    vm.introduce(ivm_compile::compile_all(
+        [Instruction::Push(ReadOperation::Local(b"def".to_vec()))],
+        &vm.options,
+    ));
+    vm.mem_pool.reserve(1 << 16);
+
+    vm.continue_execution(&mut env).unwrap();
+
+    let entries = vm.stack.iter().collect::<Vec<_>>();
+    assert_eq!(entries, [&b"abc"[..], &[0, 0, 0, 0], b"def"]);
+    assert_eq!(env.ctx.ext_a_slice(&vm.mem_pool), Ok(&b"ext"[..]));
+}
+
+#[test]
+fn load_image() {
+    let options = ProgramOptions::default();
+
+    let skipped = Instruction::Mutate(0, ReadOperation::Local(vec![1]));
+    let entry = ivm_ext_x32::REGISTER_RESERVED + skipped.compile(&options).len();
+
+    let body = ivm_compile::compile_all(
+        [
+            skipped,
+            Instruction::Mutate(1, ReadOperation::Local(vec![2])),
+        ],
+        &options,
+    );
+    let mut image = ProgramImage::new(options.clone(), entry as u64, body).compile(&options);
+
+    let mut vm = VmInstance::from_image(&image).unwrap();
+    assert_eq!(vm.execution_index, entry);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[..2], [0, 2]);
+
+    let err = VmInstance::from_image(&image[..8]).err().unwrap();
+    assert_eq!(err.cause(), &InvalidHeaderCause::FormatNotFulfilled);
+
+    image[5..13].copy_from_slice(&u64::MAX.to_le_bytes());
+    let err = VmInstance::from_image(&image).err().unwrap();
+    assert_eq!(err.cause(), &InvalidHeaderCause::UnrecognizedValue);
+
+    // Images of CFV 1 expect their body at a different address.
+    image[..4].copy_from_slice(&1u32.to_le_bytes());
+    let err = VmInstance::from_image(&image).err().unwrap();
+    assert_eq!(err.cause(), &InvalidHeaderCause::Unsupported);
+}
+
+#[test]
+fn image_round_trip() {
+    for ptr_len in [MemoryPointerLength::X32b, MemoryPointerLength::X64b] {
+        let options = ProgramOptions::new(1, ptr_len);
+        let body = ivm_compile::compile_all([Instruction::Jump(42)], &options);
+
+        let image = ProgramImage::new(options.clone(), 42, body);
+        let bytes = image.compile(&options);
+
+        let adapt = version_adapters::get_program_options(&bytes).unwrap();
+        assert_eq!(adapt.header_len, version_adapters::CCFV_HEADER_LEN);
+        assert_eq!(adapt.options, options);
+        assert_eq!(adapt.function_start, 42);
+
+        assert_eq!(ProgramImage::read(&bytes).unwrap(), image);
+    }
+}
+
+#[test]
+fn decode_round_trip() {
+    let local = || ReadOperation::Local(vec![1, 2, 3]);
+    let point = || ReadOperation::Point(4, 8);
+
+    let instructions = [
+        Instruction::Jump(1),
+        Instruction::Push(local()),
+        Instruction::Mutate(2, point()),
+        Instruction::ExternCall(3),
+        Instruction::Return,
+        Instruction::Call(4),
+        Instruction::LoadA(ReadOperation::Local(Vec::new())),
+        Instruction::Add(IntType::I8, 5, local(), point()),
+        Instruction::Sub(IntType::I16, 5, local(), point()),
+        Instruction::Mul(IntType::I32, 5, local(), point()),
+        Instruction::Div(IntType::I64, 5, local(), point()),
+        Instruction::Rem(IntType::I128, 5, local(), point()),
+        Instruction::Neg(IntType::U8, 5, local()),
+        Instruction::Eq(IntType::U16, 6, local(), point()),
+        Instruction::Ne(IntType::U32, 6, local(), point()),
+        Instruction::Lt(IntType::U64, 6, local(), point()),
+        Instruction::Le(IntType::U128, 6, local(), point()),
+        Instruction::Gt(IntType::I32, 6, local(), point()),
+        Instruction::Ge(IntType::U32, 6, local(), point()),
+        Instruction::Pop(8),
+        Instruction::Drop,
+        Instruction::Dup,
+        Instruction::Swap,
+        Instruction::Over,
+        Instruction::JumpIf(7, point()),
+        Instruction::JumpIfNot(7, local()),
+    ];
+
+    for ptr_len in [MemoryPointerLength::X32b, MemoryPointerLength::X64b] {
+        let options = ProgramOptions::new(1, ptr_len);
+        let bytecode = ivm_compile::compile_all(instructions.iter().cloned(), &options);
+
+        let decoded = ivm_compile::decode::decode_all(&bytecode, &options).unwrap();
+
+        let mut offset = 0;
+        for ((decoded_offset, decoded), instruction) in decoded.iter().zip(&instructions) {
+            assert_eq!(*decoded_offset, offset);
+            assert_eq!(decoded, instruction);
+            assert_eq!(
+                instruction.encoded_len(&options),
+                instruction.compile(&options).len()
+            );
+            offset += instruction.compile(&options).len();
+        }
+        assert_eq!(decoded.len(), instructions.len());
+
+        let err = ivm_compile::decode::decode_all(&bytecode[..bytecode.len() - 1], &options);
+        assert_eq!(err.unwrap_err().cause(), &DecodeErrorCause::UnexpectedEnd);
+    }
+
+    let truncated = Instruction::Jump(u32::MAX as usize + 1);
+    assert!(!truncated.fits(&MemoryPointerLength::X32b));
+    assert!(truncated.fits(&MemoryPointerLength::X64b));
+    assert!(instructions
+        .iter()
+        .all(|instruction| instruction.fits(&MemoryPointerLength::X32b)));
+
+    let options = ProgramOptions::default();
+    let mut bytecode = Instruction::Return.compile(&options);
+    bytecode.push(0xFF);
+
+    assert_eq!(
+        ivm_compile::decode::decode_all(&bytecode, &options),
+        Err(DecodeError::new(
+            1,
+            DecodeErrorCause::UnrecognizedInstruction(0xFF)
+        ))
+    );
+}
+
+#[test]
+fn assemble_program() {
+    let source = r#"
+; count down from 5, then multiply
+    mutate counter, local(i32 5)
+loop:
+    sub i32 counter, point(4, counter), local(i32 1)
+    jump_if loop, point(4, counter)
+    mul i32 result, local(i32 10), local(0x03, 0, 0, 0)
+    return
+
+counter: .zero 4
+result:  .bytes "ab", u16 0xFFFF
+"#;
+
+    let options = ProgramOptions::default();
+    let base = ivm_ext_x32::REGISTER_RESERVED;
+    let assembly = asm::assemble(source, &options, base).unwrap();
+
+    let counter = assembly.label("counter").unwrap();
+    let result = assembly.label("result").unwrap();
+    assert_eq!(
+        assembly.bytecode()[counter - base..],
+        *b"\0\0\0\0ab\xFF\xFF"
+    );
+
+    let code = &assembly.bytecode()[..counter - base];
+    let mnemonics = ivm_compile::decode::decode_all(code, &options)
+        .unwrap()
+        .iter()
+        .map(|(_, instruction)| fmt::mnemonic(instruction))
+        .collect::<Vec<_>>();
+    assert_eq!(mnemonics, ["mutate", "sub", "jump_if", "mul", "return"]);
+
+    let mut vm = VmInstance::reserve_ivm_ext_x32(options);
+    vm.introduce(assembly.into_bytecode());
+
+    let mut extern_map = IvmX32ExternMap::new();
     let mut env = ExecutionEnvironment::new(&mut extern_map);
 
-    vm.continue_execution(&mut env);
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[counter..counter + 4], [0; 4]);
+    assert_eq!(vm.mem_pool[result..result + 4], 30i32.to_le_bytes());
 }
 
-/*#[test]
+#[test]
+fn assembler_errors() {
+    let options = ProgramOptions::default();
+
+    for (source, line, column) in [
+        ("jump nowhere", 1, 6),
+        ("a:\na: return", 2, 1),
+        ("  push local(u8 256)", 1, 17),
+        ("frobnicate", 1, 1),
+        ("mutate 0 local()", 1, 10),
+        ("push local(\"abc)", 1, 12),
+        ("push local(1, 2", 1, 16),
+    ] {
+        let err = asm::assemble(source, &options, 0).unwrap_err();
+        assert_eq!((err.line(), err.column()), (line, column), "{err}");
+    }
+}
+
+#[test]
+fn program_builder() {
+    for ptr_len in [MemoryPointerLength::X32b, MemoryPointerLength::X64b] {
+        let options = ProgramOptions::new(1, ptr_len);
+        let mut builder = ProgramBuilder::new(ivm_ext_x32::REGISTER_RESERVED);
+
+        builder
+            .instruction_with_refs(Instruction::Mutate(0, local_i32(3)), [(0, "counter")])
+            .label("loop")
+            .instruction_with_refs(
+                Instruction::Sub(IntType::I32, 0, ReadOperation::Point(4, 0), local_i32(1)),
+                [(0, "counter"), (1, "counter")],
+            )
+            .instruction_with_refs(
+                Instruction::JumpIf(0, ReadOperation::Point(4, 0)),
+                [(0, "loop"), (1, "counter")],
+            )
+            .instruction(Instruction::Return)
+            .label("counter")
+            .data([0xFF; 4]);
+
+        let program = builder.build(&options).unwrap();
+        let counter = program.label("counter").unwrap();
+        assert_eq!(
+            counter,
+            ivm_ext_x32::REGISTER_RESERVED + program.bytecode().len() - 4
+        );
+
+        let mut vm = VmInstance::reserve_ivm_ext_x32(options);
+        vm.introduce(program.into_bytecode());
+
+        let mut extern_map = IvmX32ExternMap::new();
+        let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+        vm.continue_execution(&mut env).unwrap();
+        assert_eq!(vm.mem_pool[counter..], [0; 4]);
+    }
+
+    let options = ProgramOptions::default();
+
+    let err = ProgramBuilder::new(0)
+        .instruction(Instruction::Return)
+        .instruction_with_refs(Instruction::Jump(0), [(0, "missing")])
+        .build(&options)
+        .unwrap_err();
+    assert_eq!(err.entry(), 1);
+    assert_eq!(
+        err.cause(),
+        &BuildErrorCause::UndefinedLabel("missing".to_string())
+    );
+
+    let err = ProgramBuilder::new(0)
+        .label("a")
+        .instruction(Instruction::Return)
+        .label("a")
+        .build(&options)
+        .unwrap_err();
+    assert_eq!(err.entry(), 1);
+    assert_eq!(
+        err.cause(),
+        &BuildErrorCause::DuplicateLabel("a".to_string())
+    );
+
+    let err = ProgramBuilder::new(0)
+        .instruction_with_refs(Instruction::Return, [(0, "a")])
+        .label("a")
+        .build(&options)
+        .unwrap_err();
+    assert_eq!(err.cause(), &BuildErrorCause::InvalidSlot(0));
+
+    let options = ProgramOptions::new(1, MemoryPointerLength::X32b);
+    let above_u32 = u32::MAX as usize + 1;
+
+    let err = ProgramBuilder::new(above_u32)
+        .label("start")
+        .instruction_with_refs(Instruction::Jump(0), [(0, "start")])
+        .build(&options)
+        .unwrap_err();
+    assert_eq!(err.entry(), 0);
+    assert_eq!(err.cause(), &BuildErrorCause::PointerOverflow);
+
+    let err = ProgramBuilder::new(0)
+        .instruction(Instruction::Return)
+        .instruction(Instruction::Push(ReadOperation::Point(4, above_u32)))
+        .build(&options)
+        .unwrap_err();
+    assert_eq!(err.entry(), 1);
+    assert_eq!(err.cause(), &BuildErrorCause::PointerOverflow);
+}
+
+#[test]
+fn verify_bytecode() {
+    let options = ProgramOptions::default();
+    let base = ivm_ext_x32::REGISTER_RESERVED;
+
+    let mut valid = vec![
+        Instruction::Mutate(0, local_i32(1)),
+        Instruction::JumpIf(base, ReadOperation::Point(4, 0)),
+        Instruction::Jump(0),
+    ];
+    let end = base
+        + valid
+            .iter()
+            .map(|instruction| instruction.encoded_len(&options))
+            .sum::<usize>();
+    valid[2] = Instruction::Jump(end);
+
+    let bytecode = ivm_compile::compile_all(valid, &options);
+    assert_eq!(verify::verify(&bytecode, &options, base, 64), []);
+
+    let invalid = [
+        Instruction::Jump(base + 1),
+        Instruction::Mutate(62, local_i32(1)),
+        Instruction::Add(IntType::I32, 0, ReadOperation::Point(4, 64), local_i32(1)),
+        Instruction::Lt(IntType::I16, 0, local_i32(1), local_i32(2)),
+    ];
+    let offsets = invalid
+        .iter()
+        .scan(0, |offset, instruction| {
+            let start = *offset;
+            *offset += instruction.encoded_len(&options);
+            Some(start)
+        })
+        .collect::<Vec<_>>();
+
+    let mut bytecode = ivm_compile::compile_all(invalid, &options);
+    bytecode.push(ivm_compile::byte_id::I_PUSH);
+    bytecode.push(0xFF);
+
+    let diagnostics = verify::verify(&bytecode, &options, base, 64);
+    let expected = [
+        (offsets[0], DiagnosticKind::InvalidJumpTarget(base + 1)),
+        (
+            offsets[1],
+            DiagnosticKind::OutOfBounds { index: 62, len: 4 },
+        ),
+        (
+            offsets[2],
+            DiagnosticKind::OutOfBounds { index: 64, len: 4 },
+        ),
+        (
+            offsets[3],
+            DiagnosticKind::OperandSizeMismatch {
+                expected: 2,
+                found: 4,
+            },
+        ),
+        (
+            offsets[3],
+            DiagnosticKind::OperandSizeMismatch {
+                expected: 2,
+                found: 4,
+            },
+        ),
+        (
+            bytecode.len() - 1,
+            DiagnosticKind::Malformed(DecodeErrorCause::UnrecognizedReadOperation(0xFF)),
+        ),
+    ];
+
+    assert_eq!(
+        diagnostics,
+        expected.map(|(offset, kind)| Diagnostic::new(offset, kind))
+    );
+}
+
+#[test]
 fn loop_forever() {
-    let instructions = [Instruction::Jump(0)];
+    let mut vm = vm_ivm_ext_x32([Instruction::Jump(ivm_ext_x32::REGISTER_RESERVED)]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    assert_eq!(vm.run_for(&mut env, 1000), Ok(ExecutionStatus::OutOfFuel));
+    assert_eq!(vm.execution_index, ivm_ext_x32::REGISTER_RESERVED);
+}
+
+#[test]
+fn fuel_resumes_execution() {
+    let init = Instruction::Mutate(0, local_i32(3));
+    let loop_start =
+        ivm_ext_x32::REGISTER_RESERVED + init.compile(&ProgramOptions::default()).len();
+
+    let mut vm = vm_ivm_ext_x32([
+        init,
+        Instruction::Sub(IntType::I32, 0, ReadOperation::Point(4, 0), local_i32(1)),
+        Instruction::JumpIf(loop_start, ReadOperation::Point(4, 0)),
+        Instruction::Div(IntType::I32, 0, local_i32(1), ReadOperation::Point(4, 0)),
+    ]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let mut steps = 0;
+
+    let err = loop {
+        match vm.run_for(&mut env, 1) {
+            Ok(ExecutionStatus::OutOfFuel) => steps += 1,
+            Err(err) => break err,
+            status => panic!("division by zero did not trap: {status:?}"),
+        }
+    };
+
+    assert_eq!(steps, 7);
+    assert_eq!(err.cause(), &TrapCause::DivisionByZero);
+    assert_eq!(vm.execution_index, err.execution_index());
+
+    vm.mem_pool[0] = 1;
+    assert_eq!(vm.run_for(&mut env, 2), Ok(ExecutionStatus::Completed));
+    assert_eq!(vm.mem_pool[..4], [1, 0, 0, 0]);
+}
+
+#[test]
+fn step_and_breakpoints() {
+    let options = ProgramOptions::default();
+    let base = ivm_ext_x32::REGISTER_RESERVED;
+
+    let instructions = [
+        Instruction::Mutate(0, ReadOperation::Local(vec![1])),
+        Instruction::Mutate(1, ReadOperation::Local(vec![2])),
+        Instruction::Return,
+    ];
+    let second = base + instructions[0].encoded_len(&options);
+    let third = second + instructions[1].encoded_len(&options);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let mut vm = vm_ivm_ext_x32(instructions.clone());
+    vm.breakpoints.extend([base, third]);
+
+    assert_eq!(
+        vm.continue_execution(&mut env),
+        Ok(ExecutionStatus::Breakpoint(base))
+    );
+    assert_eq!(vm.mem_pool[..2], [0, 0]);
+
+    assert_eq!(
+        vm.continue_execution(&mut env),
+        Ok(ExecutionStatus::Breakpoint(third))
+    );
+    assert_eq!(vm.mem_pool[..2], [1, 2]);
+
+    assert_eq!(
+        vm.continue_execution(&mut env),
+        Ok(ExecutionStatus::Completed)
+    );
+
+    // Running out of fuel right before a breakpoint still reports it.
+    let mut vm = vm_ivm_ext_x32(instructions.clone());
+    vm.breakpoints.insert(second);
+
+    assert_eq!(vm.run_for(&mut env, 1), Ok(ExecutionStatus::OutOfFuel));
+    assert_eq!(
+        vm.run_for(&mut env, 1),
+        Ok(ExecutionStatus::Breakpoint(second))
+    );
+    assert_eq!(vm.mem_pool[..2], [1, 0]);
+
+    assert_eq!(vm.run_for(&mut env, 1), Ok(ExecutionStatus::OutOfFuel));
+    assert_eq!(vm.mem_pool[..2], [1, 2]);
+    assert_eq!(vm.execution_index, third);
+
+    let mut vm = vm_ivm_ext_x32(instructions.clone());
+    vm.breakpoints.insert(second);
+
+    assert_eq!(
+        vm.run_for(&mut env, 10),
+        Ok(ExecutionStatus::Breakpoint(second))
+    );
+
+    let step = vm.step(&mut env).unwrap().unwrap();
+    assert_eq!(step.execution_index(), second);
+    assert_eq!(step.instruction(), &instructions[1]);
+    assert!(!step.halted());
+    assert_eq!(vm.execution_index, third);
+
+    let step = vm.step(&mut env).unwrap().unwrap();
+    assert_eq!(step.instruction(), &Instruction::Return);
+    assert!(step.halted());
+
+    assert_eq!(vm.step(&mut env), Ok(None));
+
+    // Malformed instructions trap before anything is executed.
+    let mut vm = VmInstance::reserve_ivm_ext_x32(options.clone());
+    vm.introduce([ivm_compile::byte_id::I_MUTATE, 0]);
+
+    let err = vm.step(&mut env).unwrap_err();
+    assert!(matches!(err.cause(), TrapCause::OutOfBounds { .. }));
+    assert_eq!(vm.execution_index, base);
+}
+
+#[test]
+fn trace_execution() {
+    let options = ProgramOptions::default();
+
+    let instructions = [
+        Instruction::Push(ReadOperation::Local(vec![1])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_FLUSH),
+        Instruction::Div(
+            IntType::U8,
+            0,
+            ReadOperation::Local(vec![1]),
+            ReadOperation::Local(vec![0]),
+        ),
+    ];
+    let push = ivm_ext_x32::REGISTER_RESERVED;
+    let call = push + instructions[0].encoded_len(&options);
+    let div = call + instructions[1].encoded_len(&options);
+
     let mut vm = vm_ivm_ext_x32(instructions);
-    vm.continue_execution();
-}*/
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::with_tracer(&mut extern_map, WriteTracer::new(Vec::new()));
+
+    vm.continue_execution(&mut env).unwrap_err();
+
+    let trace = String::from_utf8(env.tracer.into_inner()).unwrap();
+    let expected = format!(
+        "{push}: 01 Push(Local([1])) (stack depth 0)\n\
+         {call}: 03 ExternCall(1) (stack depth 1)\n  \
+         extern call 1\n\
+         {div}: 0a Div(U8, 0, Local([1]), Local([0])) (stack depth 1)\n\
+         {div}: trap: {}\n",
+        TrapCause::DivisionByZero
+    );
+    assert_eq!(trace, expected);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let init = Instruction::Mutate(0, local_i32(10));
+    let loop_start =
+        ivm_ext_x32::REGISTER_RESERVED + init.compile(&ProgramOptions::default()).len();
+
+    let mut vm = vm_ivm_ext_x32([
+        init,
+        Instruction::Push(ReadOperation::Point(4, 0)),
+        Instruction::LoadA(ReadOperation::Point(2, 0)),
+        Instruction::Sub(IntType::I32, 0, ReadOperation::Point(4, 0), local_i32(1)),
+        Instruction::JumpIf(loop_start, ReadOperation::Point(4, 0)),
+    ]);
+    vm.memory_policy = MemoryPolicy::Grow(1024);
+    vm.breakpoints.insert(loop_start);
+    vm.call_stack.push(7);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    assert_eq!(
+        vm.run_for(&mut env, 9),
+        Ok(ExecutionStatus::Breakpoint(loop_start))
+    );
+
+    let snapshot = vm.snapshot(&env.ctx);
+    let (mut restored, ctx) = VmInstance::restore(&snapshot).unwrap();
+
+    assert_eq!(restored, vm);
+    assert_eq!(ctx, env.ctx);
+    assert_eq!(restored.snapshot(&ctx), snapshot);
+
+    let mut restored_extern_map = IvmX32ExternMap::new();
+    let mut restored_env = ExecutionEnvironment::new(&mut restored_extern_map);
+    restored_env.ctx = ctx;
+
+    vm.breakpoints.clear();
+    restored.breakpoints.clear();
+
+    assert_eq!(
+        vm.continue_execution(&mut env),
+        restored.continue_execution(&mut restored_env)
+    );
+    assert_eq!(restored, vm);
+    assert_eq!(restored.stack.len(), 10);
+
+    assert_eq!(
+        VmInstance::restore(b"IVMX").unwrap_err(),
+        SnapshotError::InvalidMagic
+    );
+    assert_eq!(
+        VmInstance::restore(&snapshot[..snapshot.len() - 1]).unwrap_err(),
+        SnapshotError::UnexpectedEnd
+    );
+
+    let mut invalid = snapshot.clone();
+    invalid[4] = 0xFF;
+    assert_eq!(
+        VmInstance::restore(&invalid).unwrap_err(),
+        SnapshotError::UnsupportedVersion(0x00FF)
+    );
+
+    let mut invalid = snapshot;
+    invalid.push(0);
+    assert_eq!(
+        VmInstance::restore(&invalid).unwrap_err(),
+        SnapshotError::TrailingBytes
+    );
+}
+
+#[test]
+fn heap_allocation() {
+    let reg_return = ReadOperation::Point(4, ivm_ext_x32::REG_RETURN);
+
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::LoadA(pack_args(&[16])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC),
+        Instruction::LoadA(reg_return.clone()),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_FREE),
+        Instruction::LoadA(reg_return.clone()),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_FREE),
+        Instruction::LoadA(pack_args(&[4])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC),
+        Instruction::LoadA(pack_args(&[1])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_FREE),
+        Instruction::Mutate(0, reg_return),
+        Instruction::Mutate(4, pack_args(&[64])),
+        Instruction::LoadA(ReadOperation::Point(8, 0)),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_REALLOC),
+        Instruction::LoadA(pack_args(&[1000])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC),
+    ]);
+    let heap_start = vm.mem_pool.len();
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+    vm.memory_policy = MemoryPolicy::Grow(heap_start + 80);
+
+    let mut run = |vm: &mut VmInstance, steps: usize| {
+        let (value, code) = run_extern(vm, &mut env, steps);
+        (value as usize, code)
+    };
+
+    assert_eq!(run(&mut vm, 2), (heap_start, 0));
+    assert_eq!(vm.mem_pool.len(), heap_start + 16);
+
+    assert_eq!(run(&mut vm, 2), (heap_start, 0));
+    assert_eq!(run(&mut vm, 2).1, ivm_ext_x32::ERR_DOUBLE_FREE);
+
+    assert_eq!(run(&mut vm, 2), (heap_start, 0));
+    assert_eq!(vm.mem_pool.len(), heap_start + 16);
+
+    assert_eq!(run(&mut vm, 2).1, ivm_ext_x32::ERR_INVALID_FREE);
+
+    assert_eq!(run(&mut vm, 4), (heap_start + 16, 0));
+    assert_eq!(vm.mem_pool.len(), heap_start + 80);
+
+    assert_eq!(run(&mut vm, 2), (0, ivm_ext_x32::ERR_OUT_OF_MEMORY));
+    assert_eq!(vm.mem_pool.len(), heap_start + 80);
+
+    assert_eq!(
+        env.ctx.heap.live().collect::<Vec<_>>(),
+        [(heap_start + 16, 64)]
+    );
+    assert_eq!(env.ctx.heap.freed().collect::<Vec<_>>(), [(heap_start, 16)]);
+
+    let (restored, ctx) = VmInstance::restore(&vm.snapshot(&env.ctx)).unwrap();
+    assert_eq!(restored, vm);
+    assert_eq!(ctx, env.ctx);
+
+    let end = vm.mem_pool.len();
+
+    for (live, freed) in [
+        (vec![(end - 8, 16)], vec![]),
+        (vec![(heap_start, 16)], vec![(heap_start + 8, 8)]),
+        (vec![(heap_start, 0)], vec![]),
+        (vec![], vec![(usize::MAX, 2)]),
+    ] {
+        let mut ctx = env.ctx.clone();
+        ctx.heap = Heap::from_parts(live, freed);
+
+        assert!(matches!(
+            VmInstance::restore(&vm.snapshot(&ctx)),
+            Err(SnapshotError::InvalidValue(_))
+        ));
+    }
+
+    // Freed blocks outside a truncated memory pool trap instead of being reused.
+    let alloc = [
+        Instruction::LoadA(pack_args(&[4])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC),
+    ];
+    let mut truncated = vm_ivm_ext_x32(alloc.clone());
+
+    let err = truncated.continue_execution(&mut env).unwrap_err();
+    assert_eq!(
+        err.cause(),
+        &TrapCause::OutOfBounds {
+            index: heap_start,
+            len: 4
+        }
+    );
+
+    // The heap does not grow the memory pool under a fixed memory policy.
+    let mut fixed = vm_ivm_ext_x32(alloc);
+    let len = fixed.mem_pool.len();
+
+    env.ctx = ExecutionContext::new();
+    fixed.continue_execution(&mut env).unwrap();
+
+    assert_eq!(fixed.mem_pool.len(), len);
+    assert_eq!(
+        read_register(&fixed, ivm_ext_x32::REG_ERROR),
+        ivm_ext_x32::ERR_OUT_OF_MEMORY
+    );
+}
+
+#[test]
+fn sandboxed_file_io() {
+    let root = std::env::temp_dir().join(format!("ivm-file-io-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("in.txt"), b"config").unwrap();
+
+    let open = |mode: u32, path: &str| {
+        ReadOperation::Local([&mode.to_le_bytes()[..], path.as_bytes()].concat())
+    };
+
+    let program = |buf: u32| {
+        extern_program([
+            (open(fs::OPEN_READ, "in.txt"), ivm_ext_x32::EXTC_FILE_OPEN),
+            (pack_args(&[0, buf, 16]), ivm_ext_x32::EXTC_FILE_READ),
+            (open(fs::OPEN_WRITE, "out.txt"), ivm_ext_x32::EXTC_FILE_OPEN),
+            (pack_args(&[1, buf, 6]), ivm_ext_x32::EXTC_FILE_WRITE),
+            (
+                pack_args(&[0, fs::SEEK_START, 2]),
+                ivm_ext_x32::EXTC_FILE_SEEK,
+            ),
+            (pack_args(&[0, buf, 16]), ivm_ext_x32::EXTC_FILE_READ),
+            (pack_args(&[0]), ivm_ext_x32::EXTC_FILE_CLOSE),
+            (pack_args(&[0]), ivm_ext_x32::EXTC_FILE_CLOSE),
+            (
+                open(fs::OPEN_READ, "../in.txt"),
+                ivm_ext_x32::EXTC_FILE_OPEN,
+            ),
+        ])
+    };
+
+    let (mut vm, buf) = vm_with_scratch(program, 16);
+
+    let mut extern_map = IvmX32ExternMap::with_file_root(&root).unwrap();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let mut run = |vm: &mut VmInstance| run_extern(vm, &mut env, 2);
+
+    assert_eq!(run(&mut vm), (0, 0));
+    assert_eq!(run(&mut vm), (6, 0));
+    assert_eq!(run(&mut vm), (1, 0));
+    assert_eq!(run(&mut vm), (6, 0));
+    assert_eq!(run(&mut vm), (2, 0));
+    assert_eq!(run(&mut vm), (4, 0));
+    assert_eq!(vm.mem_pool[buf..buf + 6], *b"nfigig");
+
+    assert_eq!(run(&mut vm).1, 0);
+    assert_eq!(run(&mut vm).1, -1);
+    assert_eq!(run(&mut vm), (0, -1));
+
+    assert_eq!(std::fs::read(root.join("out.txt")).unwrap(), b"config");
+
+    let mut sandbox = fs::FileSandbox::new(&root).unwrap();
+    assert_eq!(sandbox.max_open_files(), fs::DEFAULT_MAX_OPEN_FILES);
+    sandbox.set_max_open_files(1);
+
+    let handle = sandbox.open("in.txt", fs::OPEN_READ).unwrap();
+    assert!(sandbox.open("in.txt", fs::OPEN_READ).is_err());
+
+    sandbox.close(handle).unwrap();
+    assert_eq!(sandbox.open("in.txt", fs::OPEN_READ).unwrap(), handle);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn console_io() {
+    let mut vm = vm_ivm_ext_x32(
+        [
+            &[Instruction::Mutate(ivm_ext_x32::REG_ERROR, local_i32(-1))][..],
+            &extern_call(
+                ReadOperation::Local(b"diagnostic\n".to_vec()),
+                ivm_ext_x32::EXTC_STDERR_WRITE,
+            ),
+            &extern_program([(pack_args(&[]), ivm_ext_x32::EXTC_STDERR_FLUSH)]),
+        ]
+        .concat(),
+    );
+
+    let mut extern_map = BufferedX32ExternMap::buffered("input\n");
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+    env.ctx.ext_1 = false;
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[..4], [0; 4]);
+
+    // The destination is checked before reading, so stdin is never touched.
+    let dest = pack_args(&[u32::MAX, 4]);
+
+    for call_id in [
+        ivm_ext_x32::EXTC_STDIN_READ,
+        ivm_ext_x32::EXTC_STDIN_READ_LINE,
+    ] {
+        let mut vm = vm_ivm_ext_x32(extern_call(dest.clone(), call_id));
+
+        let err = vm.continue_execution(&mut env).unwrap_err();
+        assert_eq!(
+            err.cause(),
+            &TrapCause::OutOfBounds {
+                index: u32::MAX as usize,
+                len: 4
+            }
+        );
+    }
+
+    drop(env);
+    assert_eq!(extern_map.stderr, b"diagnostic\n");
+    assert!(extern_map.stdout.is_empty());
+    assert_eq!(extern_map.stdin.position(), 0);
+}
+
+#[test]
+fn args_and_env() {
+    let program = |buf: u32| {
+        extern_program([
+            (pack_args(&[]), ivm_ext_x32::EXTC_ARG_COUNT),
+            (pack_args(&[1]), ivm_ext_x32::EXTC_ARG_LEN),
+            (pack_args(&[1, buf, 4]), ivm_ext_x32::EXTC_ARG_READ),
+            (pack_args(&[2]), ivm_ext_x32::EXTC_ARG_LEN),
+            (pack_args(&[]), ivm_ext_x32::EXTC_ENV_COUNT),
+            (pack_args(&[0, buf, 16]), ivm_ext_x32::EXTC_ENV_READ),
+        ])
+    };
+
+    let (mut vm, buf) = vm_with_scratch(program, 16);
+
+    vm.import_env(|key| key == "PATH");
+    assert!(vm.env.keys().all(|key| key == "PATH"));
+    assert_eq!(vm.env.get("PATH"), std::env::var("PATH").ok().as_ref());
+
+    vm.set_args(["prog", "input.txt"]);
+    vm.set_env([("HOME", "/home/ivm")]);
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    let mut run = |vm: &mut VmInstance| run_extern(vm, &mut env, 2);
+
+    assert_eq!(run(&mut vm), (2, 0));
+    assert_eq!(run(&mut vm), (9, 0));
+    assert_eq!(run(&mut vm), (9, 0));
+    assert_eq!(vm.mem_pool[buf..buf + 5], *b"inpu\0");
+
+    assert_eq!(run(&mut vm), (0, ivm_ext_x32::ERR_INVALID_INDEX));
+    assert_eq!(run(&mut vm), (1, 0));
+    assert_eq!(run(&mut vm), (14, 0));
+    assert_eq!(vm.mem_pool[buf..buf + 14], *b"HOME=/home/ivm");
+
+    let (restored, _) = VmInstance::restore(&vm.snapshot(&env.ctx)).unwrap();
+    assert_eq!(restored, vm);
+}
+
+#[test]
+fn exit_status() {
+    let mut vm = vm_ivm_ext_x32(
+        extern_call(local_i32(-7), ivm_ext_x32::EXTC_EXIT)
+            .into_iter()
+            .chain([Instruction::Mutate(0, ReadOperation::Local(vec![1]))]),
+    );
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    assert_eq!(
+        vm.continue_execution(&mut env),
+        Ok(ExecutionStatus::Exited(-7))
+    );
+    assert_eq!(vm.step(&mut env), Ok(None));
+    assert_eq!(vm.run_to_exit(&mut env), ExitStatus::Code(-7));
+    assert_eq!(vm.mem_pool[0], 0);
+
+    let (mut restored, _) = VmInstance::restore(&vm.snapshot(&env.ctx)).unwrap();
+    assert_eq!(restored.exit_code, Some(-7));
+
+    restored.exit_code = None;
+    assert_eq!(restored.run_to_exit(&mut env), ExitStatus::Normal);
+    assert_eq!(restored.mem_pool[0], 1);
+
+    let mut vm = vm_ivm_ext_x32([Instruction::ExternCall(ivm_ext_x32::EXTC_EXIT)]);
+    let status = vm.run_to_exit(&mut env);
+    assert!(matches!(status, ExitStatus::Trapped(_)));
+    assert_eq!(status.code(), 1);
+}
+
+#[test]
+fn buffered_streams() {
+    let program = |buf: u32| {
+        extern_program([
+            (pack_args(&[buf, 4]), ivm_ext_x32::EXTC_STDIN_READ_LINE),
+            (
+                ReadOperation::Point(4, buf as usize),
+                ivm_ext_x32::EXTC_STDOUT_WRITE,
+            ),
+            (pack_args(&[buf, 4]), ivm_ext_x32::EXTC_STDIN_READ),
+            (
+                ReadOperation::Point(2, buf as usize),
+                ivm_ext_x32::EXTC_STDERR_WRITE,
+            ),
+        ])
+    };
+
+    let (mut vm, buf) = vm_with_scratch(program, 4);
+
+    let mut extern_map = BufferedX32ExternMap::buffered(*b"first line\nok");
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[ivm_ext_x32::REG_RETURN], 2);
+
+    assert_eq!(extern_map.stdout, b"firs");
+    assert_eq!(extern_map.stderr, b"ok");
+}
+
+#[test]
+fn extern_registry() {
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::LoadA(ReadOperation::Local(b"x32\n".to_vec())),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_WRITE),
+        Instruction::ExternCall(1000),
+        Instruction::ExternCall(2005),
+        Instruction::ExternCall(3000),
+    ]);
+
+    let mut x32 = BufferedX32ExternMap::buffered([]);
+    let mut custom = EmptyExternMap;
+    let mut calls = Vec::new();
+
+    let mut registry = ExternRegistry::new();
+    registry
+        .register_map(0..1000, &mut x32)
+        .unwrap()
+        .register(1000, |_, vm| {
+            vm.mem_pool[0] = 1;
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(
+        registry.register(1000, |_, _| Ok(())).err(),
+        Some(RegistryError::Collision {
+            range: 1000..1001,
+            existing: 1000..1001
+        })
+    );
+    assert_eq!(
+        registry.register_map(500..1500, &mut custom).err(),
+        Some(RegistryError::Collision {
+            range: 500..1500,
+            existing: 0..1000
+        })
+    );
+
+    let mut custom = EmptyExternMap;
+    registry.register_map(2000..2010, &mut custom).unwrap();
+    assert!(registry.contains(2009));
+    assert!(!registry.contains(2010));
+
+    let mut env = ExecutionEnvironment::new(&mut registry);
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.cause(), &TrapCause::UnrecognizedExtern(2005));
+    assert_eq!(vm.mem_pool[0], 1);
+
+    vm.execution_index += 1 + 4;
+    registry.set_fallback(|_, call_id, _| {
+        calls.push(call_id);
+        Ok(())
+    });
+
+    let mut env = ExecutionEnvironment::new(&mut registry);
+    vm.continue_execution(&mut env).unwrap();
+    drop(registry);
+
+    assert_eq!(calls, [3000]);
+    assert_eq!(x32.stdout, b"x32\n");
+}
+
+#[test]
+fn guarded_extern_policies() {
+    let write = |data: &[u8], call_id| extern_call(ReadOperation::Local(data.to_vec()), call_id);
+
+    let mut vm = vm_ivm_ext_x32(
+        [
+            write(b"a", ivm_ext_x32::EXTC_STDOUT_WRITE),
+            write(b"b", ivm_ext_x32::EXTC_STDOUT_WRITE),
+            write(b"c", ivm_ext_x32::EXTC_STDOUT_WRITE),
+            write(b"1234", ivm_ext_x32::EXTC_STDERR_WRITE),
+            write(b"56", ivm_ext_x32::EXTC_STDERR_WRITE),
+            write(b"", ivm_ext_x32::EXTC_STDOUT_FLUSH),
+        ]
+        .concat(),
+    );
+
+    let mut x32 = BufferedX32ExternMap::buffered([]);
+    let mut guarded = GuardedExternMap::empty(
+        &mut x32,
+        false,
+        IllegalOperationHandleMethod::WriteError(-9),
+    );
+
+    guarded.set_quota(
+        ivm_ext_x32::EXTC_STDOUT_WRITE,
+        CallQuota {
+            max_calls: Some(2),
+            max_bytes: None,
+        },
+    );
+    guarded.set_quota(
+        ivm_ext_x32::EXTC_STDERR_WRITE,
+        CallQuota {
+            max_calls: None,
+            max_bytes: Some(5),
+        },
+    );
+    guarded.set_byte_counter(ivm_ext_x32::written_len);
+    guarded.set_hook(|_, call_id, _| call_id != ivm_ext_x32::EXTC_STDOUT_FLUSH);
+    guarded.enable_audit_log(16);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[..4], (-9i32).to_le_bytes());
+
+    let denials = guarded
+        .audit_log()
+        .map(|entry| (entry.call_id(), entry.denial()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        denials,
+        [
+            (ivm_ext_x32::EXTC_STDOUT_WRITE, None),
+            (ivm_ext_x32::EXTC_STDOUT_WRITE, None),
+            (ivm_ext_x32::EXTC_STDOUT_WRITE, Some(Denial::CallQuota)),
+            (ivm_ext_x32::EXTC_STDERR_WRITE, None),
+            (ivm_ext_x32::EXTC_STDERR_WRITE, Some(Denial::ByteQuota)),
+            (ivm_ext_x32::EXTC_STDOUT_FLUSH, Some(Denial::Hook)),
+        ]
+    );
+    assert_eq!(
+        guarded.audit_log().last().unwrap().execution_index(),
+        vm.mem_pool.len()
+    );
+    drop(guarded);
+
+    assert_eq!(x32.stdout, b"ab");
+    assert_eq!(x32.stderr, b"1234");
+
+    let mut vm = vm_ivm_ext_x32(write(b"a", ivm_ext_x32::EXTC_STDOUT_WRITE));
+    let mut guarded = GuardedExternMap::new(
+        &mut x32,
+        vec![ivm_ext_x32::EXTC_STDOUT_WRITE],
+        false,
+        IllegalOperationHandleMethod::Trap,
+    );
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(
+        err.cause(),
+        &TrapCause::ExternDenied(ivm_ext_x32::EXTC_STDOUT_WRITE)
+    );
+    drop(guarded);
+
+    // Byte quotas count the bytes written, rather than the size of the arguments.
+    let mut vm = vm_ivm_ext_x32(
+        [
+            &push_args(&[0, 0, 1000])[..],
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_FILE_WRITE)],
+            &push_args(&[0, 0, 8]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_FILE_WRITE)],
+            &write(&[b'x'; 64], ivm_ext_x32::EXTC_STDOUT_WRITE),
+            &write(
+                &[0u32, 0, 1000].map(u32::to_le_bytes).concat(),
+                ivm_ext_x32::EXTC_FILE_WRITE,
+            ),
+        ]
+        .concat(),
+    );
+    let mut guarded =
+        GuardedExternMap::empty(&mut x32, false, IllegalOperationHandleMethod::SilentFail);
+
+    for call_id in [ivm_ext_x32::EXTC_STDOUT_WRITE, ivm_ext_x32::EXTC_FILE_WRITE] {
+        guarded.set_quota(
+            call_id,
+            CallQuota {
+                max_calls: None,
+                max_bytes: Some(16),
+            },
+        );
+    }
+    guarded.set_byte_counter(ivm_ext_x32::written_len);
+    guarded.set_stack_effect(ivm_ext_x32::stack_effect);
+    guarded.enable_audit_log(16);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm.continue_execution(&mut env).unwrap();
+
+    // The denied call consumed its arguments, and pushed a result like the allowed call.
+    let entries = vm.stack.iter().collect::<Vec<_>>();
+    assert_eq!(entries, [0u32.to_le_bytes(); 2]);
+
+    let denials = guarded
+        .audit_log()
+        .map(|entry| entry.denial())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        denials,
+        [
+            Some(Denial::ByteQuota),
+            None,
+            Some(Denial::ByteQuota),
+            Some(Denial::ByteQuota)
+        ]
+    );
+    drop(guarded);
+
+    // Calls denied by the hook do not use up their quota.
+    let mut vm = vm_ivm_ext_x32((0..3).flat_map(|_| write(b"a", ivm_ext_x32::EXTC_STDOUT_WRITE)));
+    let mut guarded =
+        GuardedExternMap::empty(&mut x32, false, IllegalOperationHandleMethod::SilentFail);
+
+    guarded.set_quota(
+        ivm_ext_x32::EXTC_STDOUT_WRITE,
+        CallQuota {
+            max_calls: Some(1),
+            max_bytes: Some(1),
+        },
+    );
+
+    guarded.set_byte_counter(ivm_ext_x32::written_len);
+
+    let mut first = true;
+    guarded.set_hook(move |_, _, _| !std::mem::take(&mut first));
+    guarded.enable_audit_log(16);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm.continue_execution(&mut env).unwrap();
+
+    let denials = guarded
+        .audit_log()
+        .map(|entry| entry.denial())
+        .collect::<Vec<_>>();
+    assert_eq!(denials, [Some(Denial::Hook), None, Some(Denial::CallQuota)]);
+    drop(guarded);
+
+    // Calls of unknown size are denied by byte quotas.
+    let program = [
+        write(b"a", ivm_ext_x32::EXTC_STDOUT_WRITE),
+        write(b"", ivm_ext_x32::EXTC_STDOUT_FLUSH),
+    ]
+    .concat();
+    let mut guarded =
+        GuardedExternMap::empty(&mut x32, false, IllegalOperationHandleMethod::SilentFail);
+
+    for call_id in [
+        ivm_ext_x32::EXTC_STDOUT_WRITE,
+        ivm_ext_x32::EXTC_STDOUT_FLUSH,
+    ] {
+        guarded.set_quota(
+            call_id,
+            CallQuota {
+                max_calls: None,
+                max_bytes: Some(16),
+            },
+        );
+    }
+    guarded.enable_audit_log(16);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm_ivm_ext_x32(program.clone())
+        .continue_execution(&mut env)
+        .unwrap();
+
+    guarded.set_byte_counter(ivm_ext_x32::written_len);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm_ivm_ext_x32(program)
+        .continue_execution(&mut env)
+        .unwrap();
+
+    let denials = guarded
+        .audit_log()
+        .map(|entry| entry.denial())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        denials,
+        [
+            Some(Denial::ByteQuota),
+            Some(Denial::ByteQuota),
+            None,
+            Some(Denial::ByteQuota)
+        ]
+    );
+    drop(guarded);
+
+    // A full audit log drops its oldest entries.
+    let mut vm = vm_ivm_ext_x32((0..3).flat_map(|_| write(b"a", ivm_ext_x32::EXTC_STDOUT_WRITE)));
+    let mut guarded = GuardedExternMap::new(
+        &mut x32,
+        vec![ivm_ext_x32::EXTC_STDOUT_WRITE],
+        false,
+        IllegalOperationHandleMethod::SilentFail,
+    );
+    guarded.enable_audit_log(2);
+
+    let mut env = ExecutionEnvironment::new(&mut guarded);
+    vm.continue_execution(&mut env).unwrap();
+
+    let indices = guarded
+        .audit_log()
+        .map(|entry| entry.execution_index())
+        .collect::<Vec<_>>();
+    assert_eq!(indices.len(), 2);
+    assert!(indices[0] < indices[1]);
+    assert_eq!(*indices.last().unwrap(), vm.mem_pool.len());
+
+    guarded.disable_audit_log();
+    assert_eq!(guarded.audit_log().len(), 0);
+}
+
+#[test]
+fn stack_calling_convention() {
+    let mut vm = vm_ivm_ext_x32(
+        [
+            &[
+                Instruction::Push(ReadOperation::Local(b"stack\n".to_vec())),
+                Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_WRITE),
+            ][..],
+            &push_args(&[16]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC)],
+            &push_args(&[1, 2]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_FREE)],
+            // ext_a stays loaded, so both calls read it instead of the stack.
+            &extern_call(
+                ReadOperation::Local(b"ext_a\n".to_vec()),
+                ivm_ext_x32::EXTC_STDOUT_WRITE,
+            ),
+            &[
+                Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_WRITE),
+                Instruction::Return,
+            ],
+        ]
+        .concat(),
+    );
+    vm.memory_policy = MemoryPolicy::Grow(1024);
+
+    let mut x32 = BufferedX32ExternMap::buffered([]);
+    let mut env = ExecutionEnvironment::new(&mut x32);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert!(env.ctx.ext_a.is_some());
+
+    // FREE pops a single argument, leaving the 1 and the address returned by ALLOC.
+    let address = vm.mem_pool.len() as u32 - 16;
+    let entries = vm.stack.iter().collect::<Vec<_>>();
+    assert_eq!(entries, [address.to_le_bytes(), 1u32.to_le_bytes()]);
+    assert_eq!(env.ctx.heap.allocation_len(address as usize), Some(16));
+
+    env.ctx.ext_a = None;
+
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::Push(ReadOperation::Local((-3i32).to_le_bytes().to_vec())),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_EXIT),
+    ]);
+    assert_eq!(vm.run_to_exit(&mut env), ExitStatus::Code(-3));
+    assert!(vm.stack.is_empty());
+
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::Push(ReadOperation::Local(vec![1, 2])),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_EXIT),
+        Instruction::ExternCall(ivm_ext_x32::EXTC_EXIT),
+    ]);
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.cause(), &TrapCause::OperandSizeMismatch);
+
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert_eq!(err.cause(), &TrapCause::StackUnderflow);
+
+    drop(env);
+    assert_eq!(x32.stdout, b"stack\next_a\next_a\n");
+
+    // File, stdin, argument, environment and heap calls also pop their arguments and push their
+    // results. Results of earlier calls are used as the first argument of later ones.
+    let root = std::env::temp_dir().join(format!("ivm-stack-io-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("in.txt"), b"config").unwrap();
+
+    let program = |buf: u32| {
+        [
+            &push_args(&[fs::OPEN_READ])[..],
+            &[
+                Instruction::Push(ReadOperation::Local(b"in.txt".to_vec())),
+                Instruction::ExternCall(ivm_ext_x32::EXTC_FILE_OPEN),
+            ],
+            &push_args(&[buf, 6]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_FILE_READ)],
+            &push_args(&[buf + 6, 4]),
+            &[
+                Instruction::ExternCall(ivm_ext_x32::EXTC_STDIN_READ_LINE),
+                Instruction::ExternCall(ivm_ext_x32::EXTC_ARG_COUNT),
+            ],
+            &push_args(&[0, buf + 10, 3]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_ENV_READ)],
+            &push_args(&[16]),
+            &[Instruction::ExternCall(ivm_ext_x32::EXTC_ALLOC)],
+            &push_args(&[32]),
+            &[
+                Instruction::ExternCall(ivm_ext_x32::EXTC_REALLOC),
+                Instruction::Return,
+            ],
+        ]
+        .concat()
+    };
+
+    let (mut vm, buf) = vm_with_scratch(program, 13);
+    vm.memory_policy = MemoryPolicy::Grow(1024);
+    vm.set_args(["prog"]);
+    vm.set_env([("K", "v")]);
+
+    let mut x32 = BufferedX32ExternMap::buffered("ok\n");
+    x32.files = Some(fs::FileSandbox::new(&root).unwrap());
+    let mut env = ExecutionEnvironment::new(&mut x32);
+
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[buf..buf + 13], *b"configok\n\0K=v");
+
+    let address = vm.stack.pop().unwrap();
+    let address = u32::from_le_bytes(address.try_into().unwrap()) as usize;
+    assert_eq!(env.ctx.heap.allocation_len(address), Some(32));
+
+    let entries = vm.stack.iter().collect::<Vec<_>>();
+    assert_eq!(entries, [6u32, 3, 1, 3].map(u32::to_le_bytes));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn stack_instructions() {
+    let source = r#"
+    push local("a")
+    push local("bc")
+    over
+    swap
+    dup
+    pop out
+    drop
+    pop out2
+    return
+
+out:  .zero 2
+out2: .zero 2
+"#;
+
+    let options = ProgramOptions::default();
+    let base = ivm_ext_x32::REGISTER_RESERVED;
+    let assembly = asm::assemble(source, &options, base).unwrap();
+
+    let out = assembly.label("out").unwrap();
+    let out2 = assembly.label("out2").unwrap();
+
+    let mut vm = VmInstance::reserve_ivm_ext_x32(options);
+    vm.introduce(assembly.into_bytecode());
+
+    let mut extern_map = IvmX32ExternMap::new();
+    let mut env = ExecutionEnvironment::new(&mut extern_map);
+
+    // a, bc -> a, bc, a -> a, a, bc -> a, a, bc, bc -> a, a, bc -> a, a -> a
+    vm.continue_execution(&mut env).unwrap();
+    assert_eq!(vm.mem_pool[out..out + 2], *b"bc");
+    assert_eq!(vm.mem_pool[out2..out2 + 2], *b"a\0");
+    assert_eq!(vm.stack.iter().collect::<Vec<_>>(), [b"a"]);
+
+    for instruction in [
+        Instruction::Pop(0),
+        Instruction::Drop,
+        Instruction::Dup,
+        Instruction::Swap,
+        Instruction::Over,
+    ] {
+        let mut vm = vm_ivm_ext_x32([
+            Instruction::Push(ReadOperation::Local(vec![1])),
+            Instruction::Drop,
+            instruction.clone(),
+        ]);
+
+        let err = vm.continue_execution(&mut env).unwrap_err();
+        assert_eq!(err.cause(), &TrapCause::StackUnderflow);
+        assert!(fmt::format_instruction(&instruction).contains(fmt::mnemonic(&instruction)));
+    }
+
+    let mut vm = vm_ivm_ext_x32([
+        Instruction::Push(ReadOperation::Local(vec![1])),
+        Instruction::Pop(usize::MAX),
+    ]);
+
+    let err = vm.continue_execution(&mut env).unwrap_err();
+    assert!(matches!(err.cause(), TrapCause::OutOfBounds { .. }));
+    assert_eq!(vm.stack.len(), 1);
+}
@@ -1,5 +1,6 @@
+use ivm_compile::decode::DecodeError;
 use ivm_compile::options::ProgramOptions;
-use ivm_compile::{Compile, Instruction, ReadOperation};
+use ivm_compile::{Compile, Instruction, IntType, ReadOperation};
 
 fn format_read_op(read_op: &ReadOperation) -> String {
     match read_op {
@@ -22,31 +23,122 @@ fn fmt_ptr(ptr: usize) -> String {
     format!("\x1b[91m\x1b[1m{ptr}")
 }
 
+fn fmt_int_type(int_type: &IntType) -> String {
+    format!("\x1b[96m{}\x1b[0m", format!("{int_type:?}").to_lowercase())
+}
+
+/// Get the mnemonic of the given instruction.
+///
+/// These are the names used by [crate::asm].
+pub const fn mnemonic(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Push(_) => "push",
+        Instruction::Jump(_) => "jump",
+        Instruction::Mutate(_, _) => "mutate",
+        Instruction::Return => "return",
+        Instruction::ExternCall(_) => "extern_call",
+        Instruction::Call(_) => "call",
+        Instruction::LoadA(_) => "load_a",
+        Instruction::Add(..) => "add",
+        Instruction::Sub(..) => "sub",
+        Instruction::Mul(..) => "mul",
+        Instruction::Div(..) => "div",
+        Instruction::Rem(..) => "rem",
+        Instruction::Neg(..) => "neg",
+        Instruction::Eq(..) => "eq",
+        Instruction::Ne(..) => "ne",
+        Instruction::Lt(..) => "lt",
+        Instruction::Le(..) => "le",
+        Instruction::Gt(..) => "gt",
+        Instruction::Ge(..) => "ge",
+        Instruction::JumpIf(..) => "jump_if",
+        Instruction::JumpIfNot(..) => "jump_if_not",
+        Instruction::Pop(_) => "pop",
+        Instruction::Drop => "drop",
+        Instruction::Dup => "dup",
+        Instruction::Swap => "swap",
+        Instruction::Over => "over",
+    }
+}
+
+fn get_instruction_color(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Push(_)
+        | Instruction::Pop(_)
+        | Instruction::Drop
+        | Instruction::Dup
+        | Instruction::Swap
+        | Instruction::Over => "\x1b[91m",
+        Instruction::Jump(_) | Instruction::JumpIf(..) | Instruction::JumpIfNot(..) => "\x1b[92m",
+        Instruction::Mutate(_, _) => "\x1b[93m",
+        Instruction::Return => "\x1b[34m",
+        Instruction::ExternCall(_) => "\x1b[95m",
+        Instruction::Call(_) => "\x1b[36m",
+        Instruction::LoadA(_) => "\x1b[33m",
+        Instruction::Add(..)
+        | Instruction::Sub(..)
+        | Instruction::Mul(..)
+        | Instruction::Div(..)
+        | Instruction::Rem(..)
+        | Instruction::Neg(..) => "\x1b[32m",
+        Instruction::Eq(..)
+        | Instruction::Ne(..)
+        | Instruction::Lt(..)
+        | Instruction::Le(..)
+        | Instruction::Gt(..)
+        | Instruction::Ge(..) => "\x1b[35m",
+    }
+}
+
 fn get_instruction_prefix(instruction: &Instruction) -> String {
-    format!(
-        "\x1b[1m{}\x1b[0m",
-        match instruction {
-            Instruction::Push(_) => "\x1b[91mpush",
-            Instruction::Jump(_) => "\x1b[92mjump",
-            Instruction::Mutate(_, _) => "\x1b[93mmutate",
-            Instruction::Return => "\x1b[34mreturn",
-            Instruction::ExternCall(_) => "\x1b[95mextern_call",
-            Instruction::Call(_) => "\x1b[36mcall",
-            Instruction::LoadA(_) => "\x1b[33mload %a%",
-        }
-    )
+    let name = match instruction {
+        Instruction::LoadA(_) => "load %a%",
+        _ => mnemonic(instruction),
+    };
+
+    format!("\x1b[1m{}{name}\x1b[0m", get_instruction_color(instruction))
 }
 
 fn display_value(instruction: &Instruction) -> String {
     match instruction {
         Instruction::Push(rd) | Instruction::LoadA(rd) => format_read_op(rd),
 
-        Instruction::ExternCall(ptr) | Instruction::Jump(ptr) | Instruction::Call(ptr) => {
-            fmt_ptr(*ptr)
-        }
+        Instruction::ExternCall(ptr)
+        | Instruction::Jump(ptr)
+        | Instruction::Call(ptr)
+        | Instruction::Pop(ptr) => fmt_ptr(*ptr),
 
         Instruction::Mutate(ptr, rd) => format!("{} -> {}", fmt_ptr(*ptr), format_read_op(rd)),
 
+        Instruction::Add(int_type, ptr, lhs, rhs)
+        | Instruction::Sub(int_type, ptr, lhs, rhs)
+        | Instruction::Mul(int_type, ptr, lhs, rhs)
+        | Instruction::Div(int_type, ptr, lhs, rhs)
+        | Instruction::Rem(int_type, ptr, lhs, rhs)
+        | Instruction::Eq(int_type, ptr, lhs, rhs)
+        | Instruction::Ne(int_type, ptr, lhs, rhs)
+        | Instruction::Lt(int_type, ptr, lhs, rhs)
+        | Instruction::Le(int_type, ptr, lhs, rhs)
+        | Instruction::Gt(int_type, ptr, lhs, rhs)
+        | Instruction::Ge(int_type, ptr, lhs, rhs) => format!(
+            "{} {} -> {}, {}",
+            fmt_int_type(int_type),
+            fmt_ptr(*ptr),
+            format_read_op(lhs),
+            format_read_op(rhs)
+        ),
+
+        Instruction::Neg(int_type, ptr, rd) => format!(
+            "{} {} -> {}",
+            fmt_int_type(int_type),
+            fmt_ptr(*ptr),
+            format_read_op(rd)
+        ),
+
+        Instruction::JumpIf(ptr, rd) | Instruction::JumpIfNot(ptr, rd) => {
+            format!("{} ? {}", fmt_ptr(*ptr), format_read_op(rd))
+        }
+
         _ => unreachable!(),
     }
 }
@@ -56,7 +148,11 @@ pub fn format_instruction(instruction: &Instruction) -> String {
     let name = get_instruction_prefix(instruction);
 
     match instruction {
-        Instruction::Return => name,
+        Instruction::Return
+        | Instruction::Drop
+        | Instruction::Dup
+        | Instruction::Swap
+        | Instruction::Over => name,
         _ => format!("{} {}\x1b[0m", name, display_value(instruction)),
     }
 }
@@ -86,3 +182,21 @@ pub fn print_instructions<'a, I>(
         println!("\x1b[30m| bytecode: {raw}\x1b[0m");
     }
 }
+
+/// Decode the given bytecode, then print its instructions.
+///
+/// See [print_instructions].
+pub fn print_bytecode(
+    program_options: &ProgramOptions,
+    bytecode: &[u8],
+    show_bytecode: bool,
+) -> Result<(), DecodeError> {
+    let instructions = ivm_compile::decode::decode_all(bytecode, program_options)?;
+
+    print_instructions(
+        program_options,
+        instructions.iter().map(|(_, instruction)| instruction),
+        show_bytecode,
+    );
+    Ok(())
+}
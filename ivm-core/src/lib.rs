@@ -1,4 +1,5 @@
 pub mod ansi;
+pub mod asm;
 pub mod cli;
 pub mod fmt;
 
@@ -0,0 +1,140 @@
+//! Integer arithmetic backing the arithmetic instructions.
+//!
+//! Operands are read as little endian integers of the instruction's [IntType].
+
+use ivm_compile::IntType;
+
+use crate::trap::TrapCause;
+
+/// A binary arithmetic operation.
+#[derive(Clone, Copy, Debug)]
+pub enum BinaryOperation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// An integer comparison.
+#[derive(Clone, Copy, Debug)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+pub type ArithmeticResult = Result<Vec<u8>, TrapCause>;
+
+macro_rules! read_int {
+    ($t:ty, $bytes:expr) => {
+        <$t>::from_le_bytes(
+            $bytes
+                .try_into()
+                .map_err(|_| TrapCause::OperandSizeMismatch)?,
+        )
+    };
+}
+
+macro_rules! binary_typed {
+    ($t:ty, $operation:expr, $lhs:expr, $rhs:expr) => {{
+        let lhs = read_int!($t, $lhs);
+        let rhs = read_int!($t, $rhs);
+
+        let result = match $operation {
+            BinaryOperation::Add => lhs.wrapping_add(rhs),
+            BinaryOperation::Sub => lhs.wrapping_sub(rhs),
+            BinaryOperation::Mul => lhs.wrapping_mul(rhs),
+            BinaryOperation::Div | BinaryOperation::Rem if rhs == 0 => {
+                return Err(TrapCause::DivisionByZero)
+            }
+            BinaryOperation::Div => lhs.wrapping_div(rhs),
+            BinaryOperation::Rem => lhs.wrapping_rem(rhs),
+        };
+        result.to_le_bytes().to_vec()
+    }};
+}
+
+/// Apply the binary operation to both operands, returning the little endian bytes of the result.
+pub fn binary(
+    operation: BinaryOperation,
+    int_type: IntType,
+    lhs: &[u8],
+    rhs: &[u8],
+) -> ArithmeticResult {
+    Ok(match int_type {
+        IntType::I8 => binary_typed!(i8, operation, lhs, rhs),
+        IntType::I16 => binary_typed!(i16, operation, lhs, rhs),
+        IntType::I32 => binary_typed!(i32, operation, lhs, rhs),
+        IntType::I64 => binary_typed!(i64, operation, lhs, rhs),
+        IntType::I128 => binary_typed!(i128, operation, lhs, rhs),
+        IntType::U8 => binary_typed!(u8, operation, lhs, rhs),
+        IntType::U16 => binary_typed!(u16, operation, lhs, rhs),
+        IntType::U32 => binary_typed!(u32, operation, lhs, rhs),
+        IntType::U64 => binary_typed!(u64, operation, lhs, rhs),
+        IntType::U128 => binary_typed!(u128, operation, lhs, rhs),
+    })
+}
+
+/// Compare both operands, returning the boolean byte (`0` or `1`) of the result.
+pub fn compare(
+    comparison: Comparison,
+    int_type: IntType,
+    lhs: &[u8],
+    rhs: &[u8],
+) -> ArithmeticResult {
+    macro_rules! compare_typed {
+        ($t:ty) => {{
+            let lhs = read_int!($t, lhs);
+            let rhs = read_int!($t, rhs);
+
+            match comparison {
+                Comparison::Eq => lhs == rhs,
+                Comparison::Ne => lhs != rhs,
+                Comparison::Lt => lhs < rhs,
+                Comparison::Le => lhs <= rhs,
+                Comparison::Gt => lhs > rhs,
+                Comparison::Ge => lhs >= rhs,
+            }
+        }};
+    }
+
+    let result = match int_type {
+        IntType::I8 => compare_typed!(i8),
+        IntType::I16 => compare_typed!(i16),
+        IntType::I32 => compare_typed!(i32),
+        IntType::I64 => compare_typed!(i64),
+        IntType::I128 => compare_typed!(i128),
+        IntType::U8 => compare_typed!(u8),
+        IntType::U16 => compare_typed!(u16),
+        IntType::U32 => compare_typed!(u32),
+        IntType::U64 => compare_typed!(u64),
+        IntType::U128 => compare_typed!(u128),
+    };
+    Ok(vec![result as u8])
+}
+
+/// Negate the operand, returning the little endian bytes of the result.
+pub fn negate(int_type: IntType, operand: &[u8]) -> ArithmeticResult {
+    macro_rules! negate_typed {
+        ($t:ty) => {
+            read_int!($t, operand).wrapping_neg().to_le_bytes().to_vec()
+        };
+    }
+
+    Ok(match int_type {
+        IntType::I8 => negate_typed!(i8),
+        IntType::I16 => negate_typed!(i16),
+        IntType::I32 => negate_typed!(i32),
+        IntType::I64 => negate_typed!(i64),
+        IntType::I128 => negate_typed!(i128),
+        IntType::U8 => negate_typed!(u8),
+        IntType::U16 => negate_typed!(u16),
+        IntType::U32 => negate_typed!(u32),
+        IntType::U64 => negate_typed!(u64),
+        IntType::U128 => negate_typed!(u128),
+    })
+}
@@ -0,0 +1,198 @@
+//! Sandboxed file access for extern calls.
+//!
+//! A [FileSandbox] only opens files inside its root directory. Paths are relative to the root, and
+//! may not contain `..` or be absolute. Symbolic links are resolved before checking whether the
+//! path is inside the root, so links cannot be used to escape it either.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+/// Open a file for reading.
+pub const OPEN_READ: u32 = 0;
+
+/// Open a file for writing, creating it if it does not exist and truncating it if it does.
+pub const OPEN_WRITE: u32 = 1;
+
+/// Open a file for appending, creating it if it does not exist.
+pub const OPEN_APPEND: u32 = 2;
+
+/// Open a file for reading and writing, creating it if it does not exist.
+pub const OPEN_READ_WRITE: u32 = 3;
+
+/// Seek relative to the start of a file.
+pub const SEEK_START: u32 = 0;
+
+/// Seek relative to the current position in a file.
+pub const SEEK_CURRENT: u32 = 1;
+
+/// Seek relative to the end of a file.
+pub const SEEK_END: u32 = 2;
+
+/// The default [FileSandbox::max_open_files()].
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+fn permission_denied(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+/// A table of files opened inside a root directory.
+///
+/// Files are referred to by handles, which are reused once closed.
+#[derive(Debug)]
+pub struct FileSandbox {
+    root: PathBuf,
+    files: Vec<Option<File>>,
+    max_open_files: usize,
+}
+
+impl FileSandbox {
+    /// Get the canonical root directory of this sandbox.
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the largest amount of files which may be open at the same time.
+    #[inline]
+    pub const fn max_open_files(&self) -> usize {
+        self.max_open_files
+    }
+
+    /// Set the largest amount of files which may be open at the same time.
+    ///
+    /// Files which are already open stay open, even if they exceed the new maximum.
+    #[inline]
+    pub fn set_max_open_files(&mut self, max_open_files: usize) {
+        self.max_open_files = max_open_files;
+    }
+
+    /// Resolve a path relative to the root directory.
+    ///
+    /// Returns [io::ErrorKind::PermissionDenied] if the path is absolute, contains `..`, or
+    /// resolves to a location outside the root. The file itself does not need to exist, but its
+    /// parent directory does.
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(path);
+
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(permission_denied(
+                "path must be relative to the sandbox root",
+            ));
+        }
+
+        let joined = self.root.join(relative);
+
+        let resolved = match joined.canonicalize() {
+            Ok(resolved) => resolved,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                // A dangling symbolic link could point anywhere once the file is created.
+                if joined.symlink_metadata().is_ok() {
+                    return Err(permission_denied("path is a dangling symbolic link"));
+                }
+
+                let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+                    return Err(err);
+                };
+                parent.canonicalize()?.join(name)
+            }
+            Err(err) => return Err(err),
+        };
+
+        if !resolved.starts_with(&self.root) {
+            return Err(permission_denied("path escapes the sandbox root"));
+        }
+        Ok(resolved)
+    }
+
+    /// Open the file at the given path using one of the `OPEN_*` modes, then return its handle.
+    ///
+    /// Returns an error if [Self::max_open_files()] files are already open.
+    pub fn open(&mut self, path: &str, mode: u32) -> io::Result<u32> {
+        let free = self.files.iter().position(Option::is_none);
+
+        if free.is_none() && self.files.len() >= self.max_open_files {
+            return Err(io::Error::other("too many open files"));
+        }
+
+        let mut options = OpenOptions::new();
+
+        match mode {
+            OPEN_READ => options.read(true),
+            OPEN_WRITE => options.write(true).create(true).truncate(true),
+            OPEN_APPEND => options.append(true).create(true),
+            OPEN_READ_WRITE => options.read(true).write(true).create(true).truncate(false),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unrecognized open mode",
+                ))
+            }
+        };
+
+        let file = options.open(self.resolve(path)?)?;
+
+        let handle = match free {
+            Some(handle) => {
+                self.files[handle] = Some(file);
+                handle
+            }
+            None => {
+                self.files.push(Some(file));
+                self.files.len() - 1
+            }
+        };
+
+        u32::try_from(handle).map_err(|_| io::Error::other("too many open files"))
+    }
+
+    /// Get the open file with the given handle.
+    pub fn file(&mut self, handle: u32) -> io::Result<&mut File> {
+        self.files
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file handle"))
+    }
+
+    /// Seek the file with the given handle using one of the `SEEK_*` modes, then return the new
+    /// position.
+    pub fn seek(&mut self, handle: u32, whence: u32, offset: i64) -> io::Result<u64> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+        let pos = match whence {
+            SEEK_START => SeekFrom::Start(
+                u64::try_from(offset).map_err(|_| invalid("negative seek from start"))?,
+            ),
+            SEEK_CURRENT => SeekFrom::Current(offset),
+            SEEK_END => SeekFrom::End(offset),
+            _ => return Err(invalid("unrecognized seek mode")),
+        };
+
+        self.file(handle)?.seek(pos)
+    }
+
+    /// Close the file with the given handle.
+    pub fn close(&mut self, handle: u32) -> io::Result<()> {
+        self.file(handle)?;
+        self.files[handle as usize] = None;
+        Ok(())
+    }
+
+    /// Create a new FileSandbox restricted to the given root directory.
+    ///
+    /// Returns an error if the root directory cannot be canonicalized.
+    pub fn new<P>(root: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            files: Vec::new(),
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+        })
+    }
+}
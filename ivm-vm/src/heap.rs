@@ -0,0 +1,248 @@
+//! A heap allocator managing a region at the end of the memory pool.
+//!
+//! The heap does not reserve any memory up front. When no freed block is large enough for an
+//! allocation, the memory pool is grown to fit it, as long as the [crate::MemoryPolicy] of the VM
+//! allows it.
+//!
+//! Freed blocks are merged with their neighbors, and reused by later allocations on a first-fit
+//! basis. Allocated memory is always zeroed.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::trap::TrapCause;
+use crate::{checked_range, VmInstance};
+
+/// An error returned by a [Heap] operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeapError {
+    /// The address does not point to the start of a live allocation.
+    InvalidFree,
+
+    /// The address points to memory which was already freed.
+    DoubleFree,
+
+    /// The [crate::MemoryPolicy] of the VM does not allow growing the memory pool any further.
+    OutOfMemory,
+}
+
+impl Display for HeapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFree => write!(f, "address is not a live allocation"),
+            Self::DoubleFree => write!(f, "address was already freed"),
+            Self::OutOfMemory => write!(f, "out of memory"),
+        }
+    }
+}
+
+impl Error for HeapError {}
+
+/// The result of a [Heap] operation.
+///
+/// The outer result traps if the memory of the heap lies outside the memory pool, while the inner
+/// result reports the [HeapError] to the program.
+pub type HeapResult = Result<Result<usize, HeapError>, TrapCause>;
+
+/// The state of the heap allocator.
+///
+/// Addresses are absolute memory pool indexes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heap {
+    live: BTreeMap<usize, usize>,
+    free: BTreeMap<usize, usize>,
+}
+
+impl Heap {
+    /// Get the length of the live allocation starting at the given address.
+    #[inline]
+    pub fn allocation_len(&self, address: usize) -> Option<usize> {
+        self.live.get(&address).copied()
+    }
+
+    /// Get an iterator over the address and length of every live allocation, ordered by address.
+    pub fn live(&self) -> impl ExactSizeIterator<Item = (usize, usize)> + '_ {
+        self.live.iter().map(|(address, len)| (*address, *len))
+    }
+
+    /// Get an iterator over the address and length of every freed block, ordered by address.
+    pub fn freed(&self) -> impl ExactSizeIterator<Item = (usize, usize)> + '_ {
+        self.free.iter().map(|(address, len)| (*address, *len))
+    }
+
+    /// Allocate `size` zeroed bytes in the memory pool of the given VM, then return the address of
+    /// the allocation.
+    ///
+    /// Allocations of 0 bytes are treated as allocations of 1 byte, so every allocation has a
+    /// unique address.
+    ///
+    /// Returns [TrapCause::OutOfBounds] if a freed block no longer fits in the memory pool.
+    pub fn alloc(&mut self, vm: &mut VmInstance, size: usize) -> HeapResult {
+        let size = size.max(1);
+
+        let reused = self
+            .free
+            .iter()
+            .find(|(_, len)| **len >= size)
+            .map(|(address, len)| (*address, *len));
+
+        let address = match reused {
+            Some((address, len)) => {
+                vm.mem_pool
+                    .get_mut(checked_range(address, size)?)
+                    .ok_or(TrapCause::OutOfBounds {
+                        index: address,
+                        len: size,
+                    })?
+                    .fill(0);
+
+                self.free.remove(&address);
+
+                if len > size {
+                    self.free.insert(address + size, len - size);
+                }
+                address
+            }
+            None => {
+                let address = vm.mem_pool.len();
+
+                if let Err(err) = self.grow(vm, address, size) {
+                    return Ok(Err(err));
+                }
+                address
+            }
+        };
+
+        self.live.insert(address, size);
+        Ok(Ok(address))
+    }
+
+    /// Resize the allocation at the given address to `size` bytes, then return its new address.
+    ///
+    /// The contents of the allocation are kept, up to the smaller of both sizes. If the allocation
+    /// cannot be resized in place, it is moved, and the old address is freed.
+    ///
+    /// Returns [TrapCause::OutOfBounds] if the allocation no longer fits in the memory pool.
+    pub fn realloc(&mut self, vm: &mut VmInstance, address: usize, size: usize) -> HeapResult {
+        let size = size.max(1);
+
+        let Some(len) = self.allocation_len(address) else {
+            return Ok(Err(self.free_error(address)));
+        };
+
+        if size <= len {
+            if size < len {
+                self.live.insert(address, size);
+                self.release(address + size, len - size);
+            }
+            return Ok(Ok(address));
+        }
+
+        vm.read_memory(address, len)?;
+        let src = address..address + len;
+
+        if src.end == vm.mem_pool.len() {
+            return Ok(self.grow(vm, address, size).map(|_| {
+                self.live.insert(address, size);
+                address
+            }));
+        }
+
+        let new_address = match self.alloc(vm, size)? {
+            Ok(new_address) => new_address,
+            Err(err) => return Ok(Err(err)),
+        };
+        vm.mem_pool.copy_within(src, new_address);
+
+        self.live.remove(&address);
+        self.release(address, len);
+        Ok(Ok(new_address))
+    }
+
+    /// Free the allocation at the given address.
+    pub fn free(&mut self, address: usize) -> Result<(), HeapError> {
+        match self.live.remove(&address) {
+            Some(len) => {
+                self.release(address, len);
+                Ok(())
+            }
+            None => Err(self.free_error(address)),
+        }
+    }
+
+    /// Get the error of freeing an address which is not a live allocation.
+    fn free_error(&self, address: usize) -> HeapError {
+        let freed = self
+            .free
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(start, len)| address < start + len);
+
+        if freed {
+            HeapError::DoubleFree
+        } else {
+            HeapError::InvalidFree
+        }
+    }
+
+    /// Grow the memory pool so that `size` bytes fit at the given address, according to the
+    /// [crate::MemoryPolicy] of the VM.
+    fn grow(&self, vm: &mut VmInstance, address: usize, size: usize) -> Result<(), HeapError> {
+        let len = address.checked_add(size).ok_or(HeapError::OutOfMemory)?;
+
+        vm.reserve_memory(len, address)
+            .map_err(|_| HeapError::OutOfMemory)
+    }
+
+    /// Add a block to the freed blocks, merging it with adjacent freed blocks.
+    fn release(&mut self, mut address: usize, mut len: usize) {
+        if let Some(next_len) = self.free.remove(&(address + len)) {
+            len += next_len;
+        }
+
+        let previous = self
+            .free
+            .range(..address)
+            .next_back()
+            .map(|(start, len)| (*start, *len));
+
+        if let Some((start, previous_len)) = previous {
+            if start + previous_len == address {
+                address = start;
+                len += previous_len;
+            }
+        }
+
+        self.free.insert(address, len);
+    }
+
+    /// Restore a heap from its live allocations and freed blocks.
+    ///
+    /// See [Self::live()] and [Self::freed()].
+    pub fn from_parts<L, F>(live: L, freed: F) -> Self
+    where
+        L: IntoIterator<Item = (usize, usize)>,
+        F: IntoIterator<Item = (usize, usize)>,
+    {
+        Self {
+            live: live.into_iter().collect(),
+            free: freed.into_iter().collect(),
+        }
+    }
+
+    /// Create a new, empty heap.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            live: BTreeMap::new(),
+            free: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,99 @@
+//! Loading complete bytecode images into a [VmInstance].
+//!
+//! See [ivm_compile::options::header_format_doc] for the image header format.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::{fs, io};
+
+use ivm_compile::options::{InvalidHeaderCause, InvalidHeaderError};
+use ivm_compile::version_adapters;
+
+use crate::{ivm_ext_x32, VmInstance};
+
+/// An error returned when a bytecode image file could not be loaded.
+#[derive(Debug)]
+pub enum ImageError {
+    /// The image file could not be read.
+    Io(io::Error),
+
+    /// The image header was invalid.
+    InvalidHeader(InvalidHeaderError),
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read image: {err}"),
+            Self::InvalidHeader(err) => write!(f, "invalid image header: {err}"),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<InvalidHeaderError> for ImageError {
+    fn from(err: InvalidHeaderError) -> Self {
+        Self::InvalidHeader(err)
+    }
+}
+
+impl VmInstance {
+    /// Create a new VmInstance from a complete bytecode image.
+    ///
+    /// The header is validated and its [ivm_compile::options::ProgramOptions] are used by the VM.
+    /// The body is placed after the registers reserved by [Self::reserve_ivm_ext_x32()], and the
+    /// execution index is set to the execution start declared in the header.
+    ///
+    /// The execution start is an absolute index into the memory pool, just like the targets of
+    /// [ivm_compile::Instruction::Jump]. It must not exceed the length of the memory pool.
+    ///
+    /// Images compiled for a different register layout are rejected, see
+    /// [ivm_ext_x32::REGISTER_LAYOUT_CFV].
+    pub fn from_image(image: &[u8]) -> Result<Self, InvalidHeaderError> {
+        let adapt = version_adapters::get_program_options(image)?;
+
+        if adapt.options.cfv() < ivm_ext_x32::REGISTER_LAYOUT_CFV {
+            return Err(InvalidHeaderError::new(
+                InvalidHeaderCause::Unsupported,
+                format!(
+                    "compile feature version {} uses a different register layout",
+                    adapt.options.cfv()
+                ),
+            ));
+        }
+
+        let mut vm = Self::reserve_ivm_ext_x32(adapt.options);
+        vm.introduce(image[adapt.header_len..].iter().copied());
+
+        vm.execution_index = usize::try_from(adapt.function_start)
+            .ok()
+            .filter(|start| *start <= vm.mem_pool.len())
+            .ok_or_else(|| {
+                InvalidHeaderError::new(
+                    InvalidHeaderCause::UnrecognizedValue,
+                    format!("execution start {} out of range", adapt.function_start),
+                )
+            })?;
+
+        Ok(vm)
+    }
+
+    /// Read the bytecode image at the given path, then create a new VmInstance from it.
+    ///
+    /// See [Self::from_image()].
+    pub fn from_image_file<P>(path: P) -> Result<Self, ImageError>
+    where
+        P: AsRef<Path>,
+    {
+        let image = fs::read(path)?;
+        Ok(Self::from_image(&image)?)
+    }
+}
@@ -1,11 +1,30 @@
+//! The `ivm_ext_x32` extern calls.
+//!
+//! # Calling convention
+//!
+//! Extern calls pop their arguments from the stack, and push their result back onto it. Every
+//! argument is its own stack entry, pushed in the order documented by the extern call, and every
+//! [u32] or [i32] argument must be exactly 4 bytes long. Extern calls returning a value also write
+//! it to [REG_RETURN], and extern calls which may fail write their error code to [REG_ERROR].
+//!
+//! As a faster alternative, the arguments of an extern call may be loaded into ext_a by
+//! [ivm_compile::Instruction::LoadA], concatenated in the same order. The stack is then left
+//! untouched, and results are only written to the registers. Data loaded into ext_a stays loaded,
+//! so the stack calling convention only applies to extern calls made before the first LoadA.
+//!
+//! See [ExecutionContext::uses_stack()].
+
 use std::io;
-use std::io::Write;
+use std::io::{Read, Write};
 
-use crate::{ExecutionContext, ExternMap, VmInstance};
+use crate::fs::FileSandbox;
+use crate::heap::HeapError;
+use crate::trap::TrapCause;
+use crate::{checked_range, ExecutionContext, ExternMap, ExternResult, VmInstance};
 
 /// Extern call id `0`.
 ///
-/// Pops bytes from the stack, then writes them to stdout.
+/// Writes the bytes argument to stdout.
 ///
 /// See [io::Stdout::write_all(&\[u8\])].
 pub const EXTC_STDOUT_WRITE: usize = 0;
@@ -23,24 +42,271 @@ pub const EXTC_STDOUT_FLUSH: usize = 1;
 /// This will cause the VM to return out of the [crate::VmInstance::continue_execution()] function.
 ///
 /// This does not prevent the container of the VM from continuing execution.
+///
+/// Prefer [EXTC_EXIT], which stops execution without moving the execution index and reports an
+/// exit code.
 pub const EXTC_JUMP_OVERFLOW: usize = 2;
 
+/// Extern call id `3`.
+///
+/// Allocates the amount of bytes given as a [u32] argument on the heap, then returns the address of
+/// the zeroed allocation.
+///
+/// The heap only grows the memory pool if the [crate::MemoryPolicy] of the VM allows it.
+///
+/// See [crate::heap::Heap::alloc()].
+pub const EXTC_ALLOC: usize = 3;
+
+/// Extern call id `4`.
+///
+/// Resizes the allocation at the address given as a [u32] argument, to the size given as a
+/// following [u32] argument, then returns the new address of the allocation.
+///
+/// See [crate::heap::Heap::realloc()].
+pub const EXTC_REALLOC: usize = 4;
+
+/// Extern call id `5`.
+///
+/// Frees the allocation at the address given as a [u32] argument.
+///
+/// See [crate::heap::Heap::free()].
+pub const EXTC_FREE: usize = 5;
+
+/// Extern call id `6`.
+///
+/// Opens the file at the path given as a bytes argument, using the mode given as a preceding [u32]
+/// argument, then returns the handle of the file.
+///
+/// The path must be UTF-8, and relative to the root of the [FileSandbox] of the extern map. See
+/// [FileSandbox::open()] for the available modes.
+pub const EXTC_FILE_OPEN: usize = 6;
+
+/// Extern call id `7`.
+///
+/// Reads from the file with the handle given as a [u32] argument, into the memory pool at the index
+/// and of the length given as following [u32] arguments, then returns the amount of bytes read.
+///
+/// See [io::Read::read(&mut \[u8\])].
+pub const EXTC_FILE_READ: usize = 7;
+
+/// Extern call id `8`.
+///
+/// Writes to the file with the handle given as a [u32] argument, from the memory pool at the index
+/// and of the length given as following [u32] arguments, then returns the amount of bytes written.
+///
+/// See [io::Write::write_all(&\[u8\])].
+pub const EXTC_FILE_WRITE: usize = 8;
+
+/// Extern call id `9`.
+///
+/// Seeks the file with the handle given as a [u32] argument, using the mode given as a following
+/// [u32] argument and the offset given as a following [i32] argument, then returns the new
+/// position.
+///
+/// See [FileSandbox::seek()].
+pub const EXTC_FILE_SEEK: usize = 9;
+
+/// Extern call id `10`.
+///
+/// Closes the file with the handle given as a [u32] argument.
+pub const EXTC_FILE_CLOSE: usize = 10;
+
+/// Extern call id `11`.
+///
+/// Reads from stdin into the memory pool at the index and of the length given as [u32] arguments,
+/// then returns the amount of bytes read.
+///
+/// See [io::Stdin::read(&mut \[u8\])].
+pub const EXTC_STDIN_READ: usize = 11;
+
+/// Extern call id `12`.
+///
+/// Reads a line from stdin into the memory pool at the index and of the length given as [u32]
+/// arguments, then returns the length of the line.
+///
+/// The line includes its line feed, if any, so a length of 0 marks the end of stdin. If the line
+/// is longer than the destination, only the start of the line is written, but the full length is
+/// still returned.
+pub const EXTC_STDIN_READ_LINE: usize = 12;
+
+/// Extern call id `13`.
+///
+/// Writes the bytes argument to stderr.
+///
+/// See [io::Stderr::write_all(&\[u8\])].
+pub const EXTC_STDERR_WRITE: usize = 13;
+
+/// Extern call id `14`.
+///
+/// Flushes stderr.
+///
+/// See [io::Stderr::flush()].
+pub const EXTC_STDERR_FLUSH: usize = 14;
+
+/// Extern call id `15`.
+///
+/// Returns the amount of program arguments.
+///
+/// See [VmInstance::args].
+pub const EXTC_ARG_COUNT: usize = 15;
+
+/// Extern call id `16`.
+///
+/// Returns the length of the program argument at the index given as a [u32] argument.
+pub const EXTC_ARG_LEN: usize = 16;
+
+/// Extern call id `17`.
+///
+/// Reads the program argument at the index given as a [u32] argument, into the memory pool at the
+/// index and of the length given as following [u32] arguments, then returns the length of the
+/// program argument.
+///
+/// If the argument is longer than the destination, only the start of the argument is written.
+pub const EXTC_ARG_READ: usize = 17;
+
+/// Extern call id `18`.
+///
+/// Returns the amount of environment variables.
+///
+/// See [VmInstance::env].
+pub const EXTC_ENV_COUNT: usize = 18;
+
+/// Extern call id `19`.
+///
+/// Returns the length of the environment variable at the index given as a [u32] argument.
+///
+/// Environment variables are formatted as `KEY=VALUE`.
+pub const EXTC_ENV_LEN: usize = 19;
+
+/// Extern call id `20`.
+///
+/// Reads the environment variable at the index given as a [u32] argument, formatted as
+/// `KEY=VALUE`, like [EXTC_ARG_READ].
+pub const EXTC_ENV_READ: usize = 20;
+
+/// Extern call id `21`.
+///
+/// Exits the program with the exit code given as an [i32] argument.
+///
+/// See [VmInstance::exit()].
+pub const EXTC_EXIT: usize = 21;
+
+/// Get the amount of arguments the given extern call pops from the stack, and the amount of
+/// results it pushes back, under the stack calling convention.
+///
+/// Returns `None` if the extern call id is not recognized.
+pub const fn stack_effect(call_id: usize) -> Option<(usize, usize)> {
+    Some(match call_id {
+        EXTC_STDOUT_FLUSH | EXTC_JUMP_OVERFLOW | EXTC_STDERR_FLUSH => (0, 0),
+        EXTC_STDOUT_WRITE | EXTC_STDERR_WRITE | EXTC_FREE | EXTC_FILE_CLOSE | EXTC_EXIT => (1, 0),
+        EXTC_ARG_COUNT | EXTC_ENV_COUNT => (0, 1),
+        EXTC_ALLOC | EXTC_ARG_LEN | EXTC_ENV_LEN => (1, 1),
+        EXTC_REALLOC | EXTC_FILE_OPEN | EXTC_STDIN_READ | EXTC_STDIN_READ_LINE => (2, 1),
+        EXTC_FILE_READ | EXTC_FILE_WRITE | EXTC_FILE_SEEK | EXTC_ARG_READ | EXTC_ENV_READ => (3, 1),
+        _ => return None,
+    })
+}
+
+/// Get the amount of bytes the given extern call writes, without consuming its arguments.
+///
+/// Returns `None` if the extern call does not write bytes. Malformed arguments count as 0 bytes, as
+/// the extern call traps before writing anything.
+///
+/// See [crate::security::GuardedExternMap::set_byte_counter()].
+pub fn written_len(ctx: &ExecutionContext, call_id: usize, vm: &VmInstance) -> Option<u64> {
+    let data = match ctx.ext_a {
+        Some(_) => ctx.ext_a_slice(&vm.mem_pool).ok(),
+        None => vm.stack.peek(),
+    };
+
+    let len = match call_id {
+        EXTC_STDOUT_WRITE | EXTC_STDERR_WRITE => data.map(<[u8]>::len),
+
+        EXTC_FILE_WRITE => {
+            // The length is the last argument, which is on top of the stack if not loaded.
+            let len = match ctx.ext_a {
+                Some(_) => data.and_then(|data| data.get(8..)),
+                None => data,
+            };
+
+            len.and_then(|len| <[u8; 4]>::try_from(len).ok())
+                .map(|len| u32::from_le_bytes(len) as usize)
+        }
+
+        _ => return None,
+    };
+    Some(len.unwrap_or(0) as u64)
+}
+
 /// The error register.
 ///
 /// Stores [i32] types, (4 bytes).
 pub const REG_ERROR: usize = 0;
 
+/// The return register.
+///
+/// Stores [u32] types, (4 bytes).
+pub const REG_RETURN: usize = 4;
+
+/// The error code written to [REG_ERROR] when freeing an address which is not a live allocation.
+pub const ERR_INVALID_FREE: i32 = -2;
+
+/// The error code written to [REG_ERROR] when freeing an address which was already freed.
+pub const ERR_DOUBLE_FREE: i32 = -3;
+
+/// The error code written to [REG_ERROR] when the heap cannot grow any further.
+pub const ERR_OUT_OF_MEMORY: i32 = -4;
+
+/// The error code written to [REG_ERROR] when a program argument or environment variable does not
+/// exist.
+pub const ERR_INVALID_INDEX: i32 = -5;
+
 /// How many bytes the VM should reserve purely for registers.
 ///
-/// Although this value will likely change in the future, this does not sacrifice
-/// backwards-compatibility, because the VM does not interfere, let alone *acknowledge* custom
-/// memory registries as such.
-pub const REGISTER_RESERVED: usize = 4;
+/// Programs are placed after the registers, so changing this value moves every absolute address of
+/// a program. See [REGISTER_LAYOUT_CFV].
+pub const REGISTER_RESERVED: usize = 8;
+
+/// The earliest compile feature version whose programs expect [REGISTER_RESERVED] bytes of
+/// registers.
+///
+/// Programs compiled for an earlier version expect 4 bytes of registers, and are rejected by
+/// [VmInstance::from_image()].
+pub const REGISTER_LAYOUT_CFV: u32 = 2;
 
 /// Copy the data into the memory pool at the given register index.
+///
+/// Returns [TrapCause::OutOfBounds] if the memory pool does not reserve the register.
 #[inline]
-pub fn write_register(reg_index: usize, data: &[u8], mem_pool: &mut [u8]) {
-    mem_pool[reg_index..(data.len() + reg_index)].copy_from_slice(data)
+pub fn write_register(reg_index: usize, data: &[u8], mem_pool: &mut [u8]) -> ExternResult {
+    mem_pool
+        .get_mut(reg_index..(data.len() + reg_index))
+        .ok_or(TrapCause::OutOfBounds {
+            index: reg_index,
+            len: data.len(),
+        })?
+        .copy_from_slice(data);
+    Ok(())
+}
+
+/// Write the error code to the [REG_ERROR] register in the given memory pool.
+///
+/// Writing `0i32` is skipped if the register is known to already contain it.
+pub fn write_err_register(
+    ctx: &mut ExecutionContext,
+    mem_pool: &mut [u8],
+    code: i32,
+) -> ExternResult {
+    if code == 0 {
+        if !ctx.ext_1 {
+            write_register(REG_ERROR, &0i32.to_le_bytes(), mem_pool)?;
+            ctx.ext_1 = true;
+        }
+    } else {
+        ctx.ext_1 = false;
+        write_register(REG_ERROR, &code.to_le_bytes(), mem_pool)?;
+    }
+    Ok(())
 }
 
 /// Match the given result, then write the error code to the [REG_ERROR] register in the given
@@ -51,45 +317,401 @@ pub fn write_io_err_register<T>(
     ctx: &mut ExecutionContext,
     mem_pool: &mut [u8],
     result: io::Result<T>,
-) {
-    match result {
-        Ok(_) => {
-            if !ctx.ext_1 {
-                write_register(REG_ERROR, &0i32.to_le_bytes(), mem_pool);
-                ctx.ext_1 = true;
+) -> ExternResult {
+    let code = match result {
+        Ok(_) => 0,
+        Err(err) => err.raw_os_error().unwrap_or(-1),
+    };
+    write_err_register(ctx, mem_pool, code)
+}
+
+/// Get the error code of the given heap error.
+pub const fn heap_err_code(err: HeapError) -> i32 {
+    match err {
+        HeapError::InvalidFree => ERR_INVALID_FREE,
+        HeapError::DoubleFree => ERR_DOUBLE_FREE,
+        HeapError::OutOfMemory => ERR_OUT_OF_MEMORY,
+    }
+}
+
+/// Get the next `N` [u32] arguments of the extern call as indexes.
+///
+/// See [ExecutionContext::pop_args()].
+fn args<const N: usize>(
+    ctx: &ExecutionContext,
+    vm: &mut VmInstance,
+) -> Result<[usize; N], TrapCause> {
+    ctx.pop_args(vm).map(|args| args.map(|arg| arg as usize))
+}
+
+/// Return the value of an IO result, and write its error code to [REG_ERROR].
+///
+/// On failure, or if the value does not fit a [u32], `0u32` is returned.
+fn write_io_result<T>(
+    ctx: &mut ExecutionContext,
+    vm: &mut VmInstance,
+    result: io::Result<T>,
+) -> ExternResult
+where
+    T: TryInto<u32>,
+{
+    let result = result.and_then(|value| {
+        value
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "value does not fit a u32"))
+    });
+
+    let value = result.as_ref().map_or(0, |value| *value);
+
+    write_register(REG_RETURN, &value.to_le_bytes(), &mut vm.mem_pool)?;
+    ctx.push_u32(vm, value);
+    write_io_err_register(ctx, &mut vm.mem_pool, result)
+}
+
+/// Get the mutable memory range of the given index and length.
+///
+/// Returns [TrapCause::OutOfBounds] if the range does not fit in the memory pool, or if its end
+/// overflows.
+fn mem_range_mut(mem_pool: &mut [u8], index: usize, len: usize) -> Result<&mut [u8], TrapCause> {
+    mem_pool
+        .get_mut(checked_range(index, len)?)
+        .ok_or(TrapCause::OutOfBounds { index, len })
+}
+
+/// Return the value of a result, and write its error code to [REG_ERROR].
+///
+/// The value is written to [REG_RETURN], and pushed onto the stack if the extern call uses the
+/// stack calling convention. On failure, or if the value does not fit a [u32], `0u32` is returned.
+fn write_return_result(
+    ctx: &mut ExecutionContext,
+    vm: &mut VmInstance,
+    result: Result<usize, i32>,
+) -> ExternResult {
+    // Values not fitting a u32 fail like in write_io_result, which has no OS error code.
+    let (value, code) = match result.and_then(|value| u32::try_from(value).map_err(|_| -1)) {
+        Ok(value) => (value, 0),
+        Err(code) => (0, code),
+    };
+
+    write_register(REG_RETURN, &value.to_le_bytes(), &mut vm.mem_pool)?;
+    ctx.push_u32(vm, value);
+    write_err_register(ctx, &mut vm.mem_pool, code)
+}
+
+/// Copy an argument or environment variable into the memory pool at the given index, then return
+/// its full length, or [ERR_INVALID_INDEX] if it does not exist.
+fn copy_entry(
+    mem_pool: &mut [u8],
+    entry: Option<&[u8]>,
+    index: usize,
+    len: usize,
+) -> Result<Result<usize, i32>, TrapCause> {
+    let dest = mem_range_mut(mem_pool, index, len)?;
+
+    Ok(entry
+        .map(|entry| {
+            let copied = entry.len().min(len);
+            dest[..copied].copy_from_slice(&entry[..copied]);
+            entry.len()
+        })
+        .ok_or(ERR_INVALID_INDEX))
+}
+
+/// Get the environment variable at the given index, formatted as `KEY=VALUE`.
+fn env_entry(vm: &VmInstance, index: usize) -> Option<Vec<u8>> {
+    vm.env
+        .iter()
+        .nth(index)
+        .map(|(key, value)| format!("{key}={value}").into_bytes())
+}
+
+/// Read a line, including its line feed, from the given source into the destination.
+///
+/// The source is read one byte at a time, so no bytes after the line are consumed.
+fn read_line<R>(source: &mut R, dest: &mut Vec<u8>) -> io::Result<usize>
+where
+    R: Read,
+{
+    let start = dest.len();
+
+    let mut byte = [0];
+
+    loop {
+        match source.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => {
+                dest.push(byte[0]);
+
+                if byte[0] == b'\n' {
+                    break;
+                }
             }
-        }
-        Err(err) => {
-            ctx.ext_1 = false;
-
-            write_register(
-                REG_ERROR,
-                &err.raw_os_error().unwrap_or(-1).to_le_bytes(),
-                mem_pool,
-            );
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
         }
     }
+    Ok(dest.len() - start)
 }
 
 /// The `ivm_ext_x32` extern map.
 ///
 /// This extern map may rely on memory registers defined in [this module](self).
-pub struct IvmX32ExternMap;
+///
+/// The standard streams of the program are generic, so output can be captured, and input can be
+/// provided by the host. By default, the standard streams of the host process are used. See
+/// [BufferedX32ExternMap] for an in-memory variant.
+#[derive(Debug)]
+pub struct IvmX32ExternMap<O = io::Stdout, E = io::Stderr, I = io::Stdin>
+where
+    O: Write,
+    E: Write,
+    I: Read,
+{
+    /// The sandbox used by file extern calls.
+    ///
+    /// If no sandbox is set, every file extern call fails with a permission error.
+    pub files: Option<FileSandbox>,
+
+    /// The sink written to by [EXTC_STDOUT_WRITE].
+    pub stdout: O,
+
+    /// The sink written to by [EXTC_STDERR_WRITE].
+    pub stderr: E,
+
+    /// The source read from by [EXTC_STDIN_READ] and [EXTC_STDIN_READ_LINE].
+    pub stdin: I,
+}
 
-impl ExternMap for IvmX32ExternMap {
-    fn handle(&mut self, ctx: &mut ExecutionContext, call_id: usize, vm: &mut VmInstance) {
+/// An [IvmX32ExternMap] writing its output to buffers, and reading its input from a buffer.
+pub type BufferedX32ExternMap = IvmX32ExternMap<Vec<u8>, Vec<u8>, io::Cursor<Vec<u8>>>;
+
+impl<O, E, I> IvmX32ExternMap<O, E, I>
+where
+    O: Write,
+    E: Write,
+    I: Read,
+{
+    fn files(&mut self) -> io::Result<&mut FileSandbox> {
+        self.files.as_mut().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::PermissionDenied, "file access is disabled")
+        })
+    }
+
+    /// Create a new IvmX32ExternMap without file access, using the given standard streams.
+    #[inline]
+    pub const fn with_streams(stdout: O, stderr: E, stdin: I) -> Self {
+        Self {
+            files: None,
+            stdout,
+            stderr,
+            stdin,
+        }
+    }
+}
+
+impl IvmX32ExternMap {
+    /// Create a new IvmX32ExternMap without file access, using the standard streams of the host
+    /// process.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_streams(io::stdout(), io::stderr(), io::stdin())
+    }
+
+    /// Create a new IvmX32ExternMap allowing file access inside the given root directory, using the
+    /// standard streams of the host process.
+    pub fn with_file_root<P>(root: P) -> io::Result<Self>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let mut extern_map = Self::new();
+        extern_map.files = Some(FileSandbox::new(root)?);
+        Ok(extern_map)
+    }
+}
+
+impl Default for IvmX32ExternMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BufferedX32ExternMap {
+    /// Create a new BufferedX32ExternMap without file access, reading the given input.
+    #[inline]
+    pub fn buffered<B>(stdin: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        Self::with_streams(Vec::new(), Vec::new(), io::Cursor::new(stdin.into()))
+    }
+}
+
+impl<O, E, I> ExternMap for IvmX32ExternMap<O, E, I>
+where
+    O: Write,
+    E: Write,
+    I: Read,
+{
+    fn handle(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        call_id: usize,
+        vm: &mut VmInstance,
+    ) -> ExternResult {
         match call_id {
             EXTC_STDOUT_WRITE => {
-                let data = ctx.ext_a_slice();
-                let res = io::stdout().write_all(data);
-                write_io_err_register(ctx, &mut vm.mem_pool, res);
+                let res = self.stdout.write_all(&ctx.pop_bytes(vm)?);
+                write_io_err_register(ctx, &mut vm.mem_pool, res)
+            }
+
+            EXTC_STDOUT_FLUSH => write_io_err_register(ctx, &mut vm.mem_pool, self.stdout.flush()),
+
+            EXTC_JUMP_OVERFLOW => {
+                vm.execution_index = vm.mem_pool.len();
+                Ok(())
+            }
+
+            EXTC_ALLOC => {
+                let [size] = args(ctx, vm)?;
+                let res = ctx.heap.alloc(vm, size)?;
+                write_return_result(ctx, vm, res.map_err(heap_err_code))
+            }
+
+            EXTC_REALLOC => {
+                let [address, size] = args(ctx, vm)?;
+                let res = ctx.heap.realloc(vm, address, size)?;
+                write_return_result(ctx, vm, res.map_err(heap_err_code))
+            }
+
+            EXTC_FREE => {
+                let [address] = args(ctx, vm)?;
+                let code = ctx.heap.free(address).map_or_else(heap_err_code, |_| 0);
+                write_err_register(ctx, &mut vm.mem_pool, code)
+            }
+
+            EXTC_FILE_OPEN => {
+                let (mode, path) = if ctx.uses_stack() {
+                    let path = ctx.pop_bytes(vm)?.into_owned();
+                    (ctx.pop_u32(vm)?, path)
+                } else {
+                    let data = ctx.ext_a_slice(&vm.mem_pool)?;
+                    let (mode, path) = data
+                        .split_first_chunk()
+                        .ok_or(TrapCause::OperandSizeMismatch)?;
+
+                    (u32::from_le_bytes(*mode), path.to_vec())
+                };
+
+                let res = std::str::from_utf8(&path)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+                    .and_then(|path| self.files()?.open(path, mode));
+
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_FILE_READ => {
+                let [handle, index, len] = args(ctx, vm)?;
+                let dest = mem_range_mut(&mut vm.mem_pool, index, len)?;
+                let res = self
+                    .files()
+                    .and_then(|files| files.file(handle as u32)?.read(dest));
+
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_FILE_WRITE => {
+                let [handle, index, len] = args(ctx, vm)?;
+                let src = mem_range_mut(&mut vm.mem_pool, index, len)?;
+                let res = self
+                    .files()
+                    .and_then(|files| files.file(handle as u32)?.write_all(src))
+                    .map(|_| len);
+
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_FILE_SEEK => {
+                let [handle, whence, offset] = args(ctx, vm)?;
+                let offset = offset as u32 as i32 as i64;
+                let res = self
+                    .files()
+                    .and_then(|files| files.seek(handle as u32, whence as u32, offset));
+
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_FILE_CLOSE => {
+                let [handle] = args(ctx, vm)?;
+                let res = self.files().and_then(|files| files.close(handle as u32));
+                write_io_err_register(ctx, &mut vm.mem_pool, res)
+            }
+
+            EXTC_STDIN_READ => {
+                let [index, len] = args(ctx, vm)?;
+                let dest = mem_range_mut(&mut vm.mem_pool, index, len)?;
+                let res = self.stdin.read(dest);
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_STDIN_READ_LINE => {
+                let [index, len] = args(ctx, vm)?;
+                let dest = mem_range_mut(&mut vm.mem_pool, index, len)?;
+
+                let mut line = Vec::new();
+                let res = read_line(&mut self.stdin, &mut line);
+
+                let copied = line.len().min(dest.len());
+                dest[..copied].copy_from_slice(&line[..copied]);
+
+                write_io_result(ctx, vm, res)
+            }
+
+            EXTC_STDERR_WRITE => {
+                let res = self.stderr.write_all(&ctx.pop_bytes(vm)?);
+                write_io_err_register(ctx, &mut vm.mem_pool, res)
             }
 
-            EXTC_STDOUT_FLUSH => write_io_err_register(ctx, &mut vm.mem_pool, io::stdout().flush()),
+            EXTC_STDERR_FLUSH => write_io_err_register(ctx, &mut vm.mem_pool, self.stderr.flush()),
 
-            EXTC_JUMP_OVERFLOW => vm.execution_index = vm.mem_pool.len(),
+            EXTC_ARG_COUNT => write_return_result(ctx, vm, Ok(vm.args.len())),
+
+            EXTC_ARG_LEN => {
+                let [index] = args(ctx, vm)?;
+                let res = vm.args.get(index).map(String::len).ok_or(ERR_INVALID_INDEX);
+                write_return_result(ctx, vm, res)
+            }
+
+            EXTC_ARG_READ => {
+                let [arg, index, len] = args(ctx, vm)?;
+                let entry = vm.args.get(arg).map(String::as_bytes);
+                let res = copy_entry(&mut vm.mem_pool, entry, index, len)?;
+                write_return_result(ctx, vm, res)
+            }
+
+            EXTC_ENV_COUNT => write_return_result(ctx, vm, Ok(vm.env.len())),
+
+            EXTC_ENV_LEN => {
+                let [index] = args(ctx, vm)?;
+                let res = env_entry(vm, index)
+                    .map(|entry| entry.len())
+                    .ok_or(ERR_INVALID_INDEX);
+                write_return_result(ctx, vm, res)
+            }
+
+            EXTC_ENV_READ => {
+                let [var, index, len] = args(ctx, vm)?;
+                let entry = env_entry(vm, var);
+                let res = copy_entry(&mut vm.mem_pool, entry.as_deref(), index, len)?;
+                write_return_result(ctx, vm, res)
+            }
+
+            EXTC_EXIT => {
+                let code = ctx.pop_i32(vm)?;
+                vm.exit(code);
+                Ok(())
+            }
 
-            _ => panic!("unrecognized ivm_x32 external '{call_id}'"),
+            _ => Err(TrapCause::UnrecognizedExtern(call_id)),
         }
     }
 }
@@ -1,79 +1,356 @@
-#![feature(slice_ptr_len)]
-#![feature(const_slice_from_raw_parts)]
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
 
-use ivm_compile::byte_id;
+use ivm_compile::decode::{Decode, DecodeErrorCause};
 use ivm_compile::options::{MemoryPointerLength, ProgramOptions};
+use ivm_compile::{byte_id, Instruction, IntType};
 
+use crate::arithmetic::{BinaryOperation, Comparison};
+use crate::heap::Heap;
+use crate::stack::Stack;
+use crate::trace::{NoopTracer, Tracer};
+use crate::trap::{TrapCause, VmError};
+
+mod arithmetic;
+pub mod fs;
+pub mod heap;
+pub mod image;
 pub mod ivm_ext_x32;
+pub mod registry;
 pub mod security;
+pub mod snapshot;
+pub mod stack;
+pub mod trace;
+pub mod trap;
+
+/// The result of an extern call.
+///
+/// If an extern call fails, the VM will trap with the returned [TrapCause].
+pub type ExternResult = Result<(), TrapCause>;
 
 pub trait ExternMap {
-    fn handle(&mut self, ctx: &mut ExecutionContext, call_id: usize, vm: &mut VmInstance);
+    fn handle(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        call_id: usize,
+        vm: &mut VmInstance,
+    ) -> ExternResult;
 }
 
+/// An extern map which does not recognize any extern call.
 pub struct EmptyExternMap;
 
 impl ExternMap for EmptyExternMap {
-    fn handle(&mut self, _ctx: &mut ExecutionContext, call_id: usize, _vm: &mut VmInstance) {
-        panic!(
-            "call ({call_id}) to an EmptyExternMap [ivm-vm/lib.rs @line {}]",
-            line!()
-        );
+    fn handle(
+        &mut self,
+        _ctx: &mut ExecutionContext,
+        call_id: usize,
+        _vm: &mut VmInstance,
+    ) -> ExternResult {
+        Err(TrapCause::UnrecognizedExtern(call_id))
     }
 }
 
-#[inline(always)]
-const fn empty_slice() -> *const [u8] {
-    std::ptr::slice_from_raw_parts(std::ptr::null(), 0)
-}
-
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExecutionContext {
-    pub ext_a: *const [u8], // The ExternMap MUST provide safety checks!
+    /// The range of the memory pool loaded by [ivm_compile::Instruction::LoadA].
+    ///
+    /// The loaded data stays in ext_a until another LoadA replaces it. Extern calls only pop their
+    /// arguments from the stack while nothing is loaded.
+    pub ext_a: Option<Range<usize>>,
     pub ext_1: bool,
     // ^ The IvmExtX32 extern map will rely on this to quickly decide whether to write to the error
     // | register.
+    /// The heap allocator used by extern calls allocating memory.
+    pub heap: Heap,
 }
 
 impl ExecutionContext {
-    #[track_caller]
-    pub fn ext_a_slice(&mut self) -> &[u8] {
-        if self.ext_a.len() == 0 {
-            panic!("ext_a is null ~ prevented dereference of nullptr (VM#00001)");
+    /// Get the data loaded into ext_a from the given memory pool.
+    ///
+    /// Returns [TrapCause::ExtANotLoaded] if no data was loaded, or [TrapCause::OutOfBounds] if the
+    /// loaded range no longer fits in the memory pool.
+    pub fn ext_a_slice<'a>(&self, mem_pool: &'a [u8]) -> Result<&'a [u8], TrapCause> {
+        let range = self.ext_a.clone().ok_or(TrapCause::ExtANotLoaded)?;
+
+        mem_pool.get(range.clone()).ok_or(TrapCause::OutOfBounds {
+            index: range.start,
+            len: range.len(),
+        })
+    }
+
+    /// Returns true if the current extern call uses the stack calling convention.
+    ///
+    /// Extern calls read their arguments from ext_a if data was loaded by
+    /// [ivm_compile::Instruction::LoadA]. Otherwise, they pop their arguments from the stack, and
+    /// push their results back onto it.
+    #[inline]
+    pub const fn uses_stack(&self) -> bool {
+        self.ext_a.is_none()
+    }
+
+    /// Get the bytes of the next argument of the current extern call.
+    ///
+    /// Returns the data loaded into ext_a, or pops the top entry of the stack. Returns
+    /// [TrapCause::StackUnderflow] if the stack is empty.
+    pub fn pop_bytes<'a>(&self, vm: &'a mut VmInstance) -> Result<Cow<'a, [u8]>, TrapCause> {
+        match self.ext_a {
+            Some(_) => self.ext_a_slice(&vm.mem_pool).map(Cow::Borrowed),
+            None => vm
+                .stack
+                .pop()
+                .map(Cow::Owned)
+                .ok_or(TrapCause::StackUnderflow),
+        }
+    }
+
+    /// Get the next `N` [u32] arguments of the current extern call, in the order they were pushed.
+    ///
+    /// If data was loaded into ext_a, it must contain exactly `N` arguments. Otherwise, `N` entries
+    /// of exactly 4 bytes each are popped from the stack. Returns [TrapCause::OperandSizeMismatch]
+    /// if the arguments are of the wrong size.
+    pub fn pop_args<const N: usize>(&self, vm: &mut VmInstance) -> Result<[u32; N], TrapCause> {
+        let to_u32 = |bytes: &[u8]| {
+            bytes
+                .try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| TrapCause::OperandSizeMismatch)
+        };
+
+        if self.ext_a.is_some() {
+            let data = self.ext_a_slice(&vm.mem_pool)?;
+
+            if data.len() != N * 4 {
+                return Err(TrapCause::OperandSizeMismatch);
+            }
+            return Ok(std::array::from_fn(|i| {
+                to_u32(&data[i * 4..i * 4 + 4]).unwrap()
+            }));
+        }
+
+        let mut args = [0; N];
+
+        for arg in args.iter_mut().rev() {
+            *arg = to_u32(&vm.stack.pop().ok_or(TrapCause::StackUnderflow)?)?;
+        }
+        Ok(args)
+    }
+
+    /// Get the next [u32] argument of the current extern call.
+    ///
+    /// See [Self::pop_args()].
+    #[inline]
+    pub fn pop_u32(&self, vm: &mut VmInstance) -> Result<u32, TrapCause> {
+        self.pop_args(vm).map(|[value]| value)
+    }
+
+    /// Get the next [i32] argument of the current extern call.
+    ///
+    /// See [Self::pop_args()].
+    #[inline]
+    pub fn pop_i32(&self, vm: &mut VmInstance) -> Result<i32, TrapCause> {
+        self.pop_u32(vm).map(|value| value as i32)
+    }
+
+    /// Push a [u32] result of the current extern call onto the stack, if the call uses the stack
+    /// calling convention.
+    ///
+    /// See [Self::uses_stack()].
+    #[inline]
+    pub fn push_u32(&self, vm: &mut VmInstance, value: u32) {
+        if self.uses_stack() {
+            vm.stack.push(&value.to_le_bytes());
         }
-        unsafe { &*self.ext_a }
+    }
+
+    /// Push an [i32] result of the current extern call onto the stack, if the call uses the stack
+    /// calling convention.
+    ///
+    /// See [Self::uses_stack()].
+    #[inline]
+    pub fn push_i32(&self, vm: &mut VmInstance, value: i32) {
+        self.push_u32(vm, value as u32);
     }
 
     #[inline(always)]
     pub const fn new() -> Self {
         Self {
-            ext_a: empty_slice(),
+            ext_a: None,
             ext_1: true,
+            heap: Heap::new(),
         }
     }
 }
 
-pub struct ExecutionEnvironment<'a> {
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The environment a [VmInstance] executes in.
+///
+/// The [Tracer] is invoked before and after each instruction and extern call. By default, the
+/// [NoopTracer] is used, which does not affect performance.
+pub struct ExecutionEnvironment<'a, T = NoopTracer>
+where
+    T: Tracer,
+{
     extern_map: &'a mut dyn ExternMap,
     pub ctx: ExecutionContext,
+    pub tracer: T,
 }
 
-impl<'a> ExecutionEnvironment<'a> {
+impl<'a, T> ExecutionEnvironment<'a, T>
+where
+    T: Tracer,
+{
     #[inline(always)]
-    pub fn call_extern(&mut self, call_id: usize, vm: &mut VmInstance) {
-        self.extern_map.handle(&mut self.ctx, call_id, vm);
+    pub fn call_extern(&mut self, call_id: usize, vm: &mut VmInstance) -> ExternResult {
+        self.tracer.before_extern(vm, &self.ctx, call_id);
+        let result = self.extern_map.handle(&mut self.ctx, call_id, vm);
+        self.tracer.after_extern(vm, &self.ctx, call_id, &result);
+        result
     }
 
-    /// Create a new ExecutionEnvironment.
+    /// Create a new ExecutionEnvironment using the given [Tracer].
     #[inline(always)]
-    pub fn new(extern_map: &'a mut dyn ExternMap) -> Self {
+    pub fn with_tracer(extern_map: &'a mut dyn ExternMap, tracer: T) -> Self {
         Self {
             extern_map,
             ctx: ExecutionContext::new(),
+            tracer,
         }
     }
 }
 
-pub type Stack = Vec<*const [u8]>;
+impl<'a> ExecutionEnvironment<'a> {
+    /// Create a new ExecutionEnvironment.
+    #[inline(always)]
+    pub fn new(extern_map: &'a mut dyn ExternMap) -> Self {
+        Self::with_tracer(extern_map, NoopTracer)
+    }
+}
+
+/// Decides how the VM handles writes past the end of the memory pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// Writes past the end of the memory pool trap with [TrapCause::OutOfBounds].
+    #[default]
+    Fixed,
+
+    /// The memory pool is grown with null (0x00) bytes to fit writes past its end, as long as the
+    /// length of the memory pool does not exceed the given limit.
+    ///
+    /// Writes exceeding the limit trap with [TrapCause::OutOfBounds].
+    Grow(usize),
+}
+
+/// Get the range of `len` bytes starting at `index`.
+///
+/// Returns [TrapCause::OutOfBounds] if the end of the range overflows.
+#[inline]
+fn checked_range(index: usize, len: usize) -> Result<Range<usize>, TrapCause> {
+    match index.checked_add(len) {
+        Some(end) => Ok(index..end),
+        None => Err(TrapCause::OutOfBounds { index, len }),
+    }
+}
+
+/// Whether execution should continue after an instruction.
+enum Flow {
+    Continue,
+    Halt,
+}
+
+/// The state of a [VmInstance] after execution stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    /// Execution halted, either by [ivm_compile::Instruction::Return] with an empty call stack, or
+    /// by reaching the end of the memory pool.
+    Completed,
+
+    /// Execution paused before the instruction at the given execution index, as it is one of the
+    /// [VmInstance::breakpoints].
+    ///
+    /// Running the VM again executes the instruction at the breakpoint, then continues as usual.
+    Breakpoint(usize),
+
+    /// The step budget was used up before execution halted.
+    ///
+    /// Running the VM again resumes execution exactly where it stopped.
+    OutOfFuel,
+
+    /// The program exited with the given exit code.
+    ///
+    /// See [VmInstance::exit()].
+    Exited(i32),
+}
+
+/// How a program ended, as returned by [VmInstance::run_to_exit()].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Execution halted without an explicit exit code.
+    Normal,
+
+    /// The program exited with the given exit code.
+    Code(i32),
+
+    /// An instruction trapped.
+    Trapped(VmError),
+}
+
+impl ExitStatus {
+    /// Get the exit code a host process should exit with.
+    ///
+    /// This is `0` for [Self::Normal], and `1` for [Self::Trapped].
+    #[inline]
+    pub const fn code(&self) -> i32 {
+        match self {
+            Self::Normal => 0,
+            Self::Code(code) => *code,
+            Self::Trapped(_) => 1,
+        }
+    }
+}
+
+/// What a single instruction did, as returned by [VmInstance::step()].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    execution_index: usize,
+    instruction: Instruction,
+    halted: bool,
+}
+
+impl Step {
+    /// Get the execution index at which the instruction started.
+    #[inline]
+    pub const fn execution_index(&self) -> usize {
+        self.execution_index
+    }
+
+    /// Get the executed instruction, as it was before execution.
+    #[inline]
+    pub const fn instruction(&self) -> &Instruction {
+        &self.instruction
+    }
+
+    /// Returns true if the instruction halted execution.
+    #[inline]
+    pub const fn halted(&self) -> bool {
+        self.halted
+    }
+
+    #[inline]
+    pub const fn new(execution_index: usize, instruction: Instruction, halted: bool) -> Self {
+        Self {
+            execution_index,
+            instruction,
+            halted,
+        }
+    }
+}
 
 /// An instance of the ivm VM.
 ///
@@ -90,23 +367,48 @@ pub type Stack = Vec<*const [u8]>;
 /// let program_options = ProgramOptions::new(1, MemoryPointerLength::X32b);
 ///
 /// let bytecode = ivm_compile::compile_all([
-///     Instruction::Push(ReadOperation::Local(b"Hello, world!".to_vec())),
+///     Instruction::LoadA(ReadOperation::Local(b"Hello, world!".to_vec())),
 ///     Instruction::ExternCall(ivm_ext_x32::EXTC_STDOUT_WRITE)
 /// ], &program_options);
 ///
-/// let mut extern_map = IvmX32ExternMap;
+/// let mut extern_map = IvmX32ExternMap::new();
 /// let mut env = ExecutionEnvironment::new(&mut extern_map);
 /// let mut vm = VmInstance::reserve_ivm_ext_x32(program_options);
 ///
 /// vm.introduce(bytecode);
-/// vm.continue_execution(&mut env);
+/// vm.continue_execution(&mut env).unwrap();
 /// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct VmInstance {
     pub options: ProgramOptions,
     pub mem_pool: Vec<u8>,
     pub execution_index: usize,
     pub stack: Stack,
     pub call_stack: Vec<usize>,
+    pub memory_policy: MemoryPolicy,
+
+    /// The execution indexes at which execution pauses with [ExecutionStatus::Breakpoint].
+    pub breakpoints: HashSet<usize>,
+
+    /// The arguments passed to the program.
+    ///
+    /// See [ivm_ext_x32::EXTC_ARG_READ].
+    pub args: Vec<String>,
+
+    /// The environment variables visible to the program, ordered by name.
+    ///
+    /// See [ivm_ext_x32::EXTC_ENV_READ].
+    pub env: BTreeMap<String, String>,
+
+    /// The exit code of the program, if it exited.
+    ///
+    /// A VM which exited does not execute any further instructions. Setting this back to `None`
+    /// allows resuming execution after the instruction which exited.
+    pub exit_code: Option<i32>,
+
+    /// The breakpoint execution last paused at, which is executed instead of reported by the next
+    /// run.
+    resume_breakpoint: Option<usize>,
 }
 
 impl VmInstance {
@@ -118,51 +420,159 @@ impl VmInstance {
         self.mem_pool.extend(bytes);
     }
 
-    /// Returns a tuple containing the read bytes, the length of the pointer, and how many bytes
-    /// were traversed.
-    fn handle_read_op(&self, mut index: usize) -> (*const [u8], usize) {
+    /// Exit the program with the given exit code, halting execution after the current instruction.
+    ///
+    /// This is meant to be called by extern calls, such as [ivm_ext_x32::EXTC_EXIT].
+    #[inline]
+    pub fn exit(&mut self, code: i32) {
+        self.exit_code = Some(code);
+    }
+
+    /// Replace the arguments passed to the program.
+    pub fn set_args<I, S>(&mut self, args: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+    }
+
+    /// Replace the environment variables visible to the program.
+    pub fn set_env<I, K, V>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = vars
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+    }
+
+    /// Make the environment variables of the host process visible to the program, if their name
+    /// is accepted by the given filter.
+    ///
+    /// Variables which are not valid UTF-8 are skipped.
+    pub fn import_env<F>(&mut self, mut filter: F)
+    where
+        F: FnMut(&str) -> bool,
+    {
+        self.env.extend(
+            std::env::vars_os()
+                .filter_map(|(key, value)| {
+                    Some((key.into_string().ok()?, value.into_string().ok()?))
+                })
+                .filter(|(key, _)| filter(key)),
+        );
+    }
+
+    /// Get `len` bytes of the memory pool, starting at the given index.
+    ///
+    /// Returns [TrapCause::OutOfBounds] if the range does not fit in the memory pool.
+    #[inline]
+    pub fn read_memory(&self, index: usize, len: usize) -> Result<&[u8], TrapCause> {
+        self.mem_pool
+            .get(checked_range(index, len)?)
+            .ok_or(TrapCause::OutOfBounds { index, len })
+    }
+
+    /// Copy the data into the memory pool at the given index.
+    ///
+    /// If the data does not fit in the memory pool, the memory pool is grown according to the
+    /// [MemoryPolicy] of this VmInstance.
+    #[inline]
+    pub fn write_memory(&mut self, index: usize, data: &[u8]) -> Result<(), TrapCause> {
+        let range = checked_range(index, data.len())?;
+        self.reserve_memory(range.end, index)?;
+
+        self.mem_pool[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Copy the bytes in the source range of the memory pool to the given index.
+    ///
+    /// See [Self::write_memory()].
+    fn copy_memory(&mut self, src: Range<usize>, index: usize) -> Result<(), TrapCause> {
+        let range = checked_range(index, src.len())?;
+        self.reserve_memory(range.end, index)?;
+
+        self.mem_pool.copy_within(src, index);
+        Ok(())
+    }
+
+    /// Ensure the memory pool is at least `len` bytes long, growing it with null (0x00) bytes if
+    /// the [MemoryPolicy] allows it.
+    ///
+    /// `index` is only used to report the failed access.
+    fn reserve_memory(&mut self, len: usize, index: usize) -> Result<(), TrapCause> {
+        if len <= self.mem_pool.len() {
+            return Ok(());
+        }
+
+        match self.memory_policy {
+            MemoryPolicy::Grow(limit) if len <= limit => {
+                self.mem_pool.resize(len, 0);
+                Ok(())
+            }
+            _ => Err(TrapCause::OutOfBounds {
+                index,
+                len: len - index,
+            }),
+        }
+    }
+
+    /// Returns a tuple containing the range of the read bytes in the memory pool and how many
+    /// bytes were traversed.
+    fn handle_read_op(&self, mut index: usize) -> Result<(Range<usize>, usize), TrapCause> {
         let span = self.options.ptr_len().get_span();
-        let identifier = self.mem_pool[index];
+        let identifier = self.read_memory(index, 1)?[0];
 
         index += 1;
 
-        let read_size = self._extract_ptr(index);
-        let mut skip = 1 + read_size;
+        let read_size = self._extract_ptr(index)?;
+        let mut skip = 1 + span;
 
         let location = match identifier {
             byte_id::RDOP_LOCAL => {
-                skip += span;
+                skip = skip.checked_add(read_size).ok_or(TrapCause::OutOfBounds {
+                    index,
+                    len: read_size,
+                })?;
                 index + span
             }
 
             byte_id::RDOP_POINT => {
-                skip += span << 1;
-                self._extract_ptr(index)
+                skip += span;
+                self._extract_ptr(index + span)?
             }
 
-            _ => panic!("unrecognized read operation '{identifier:02x}'"),
+            _ => return Err(TrapCause::UnrecognizedReadOperation(identifier)),
         };
 
-        let data = &self.mem_pool[location..][..read_size] as *const [u8];
-        (data, skip)
+        self.read_memory(location, read_size)?;
+        Ok((location..location + read_size, skip))
     }
 
     #[inline]
-    fn handle_read_op_skip(&mut self) -> *const [u8] {
-        let (data, skip) = self.handle_read_op(self.execution_index);
+    fn handle_read_op_skip(&mut self) -> Result<Range<usize>, TrapCause> {
+        let (data, skip) = self.handle_read_op(self.execution_index)?;
         self.execution_index += skip;
-        data
+        Ok(data)
     }
 
     /// Extract a pointer at the given index.
     #[inline]
-    fn _extract_ptr(&self, index: usize) -> usize {
-        self.options.ptr_len().extract(index, &self.mem_pool)
+    fn _extract_ptr(&self, index: usize) -> Result<usize, TrapCause> {
+        let ptr_len = self.options.ptr_len();
+        let bytes = self.read_memory(index, ptr_len.get_span())?;
+
+        Ok(ptr_len.to_usize(bytes))
     }
 
     /// Extract a pointer at the current execution index.
     #[inline]
-    fn extract_ptr(&self) -> usize {
+    fn extract_ptr(&self) -> Result<usize, TrapCause> {
         self._extract_ptr(self.execution_index)
     }
 
@@ -172,16 +582,196 @@ impl VmInstance {
     /// [ivm_compile::options::MemoryPointerLength::get_span()], and will vary depending on the
     /// [ProgramOptions] contained within this VmInstance.
     #[inline]
-    fn extract_ptr_skip(&mut self) -> usize {
-        let ptr = self.extract_ptr();
+    fn extract_ptr_skip(&mut self) -> Result<usize, TrapCause> {
+        let ptr = self.extract_ptr()?;
         self.execution_index += self.options.ptr_len().get_span();
-        ptr
+        Ok(ptr)
+    }
+
+    /// Extract an [IntType] at the current execution index, then skip its byte.
+    fn extract_int_type_skip(&mut self) -> Result<IntType, TrapCause> {
+        let byte = self.read_memory(self.execution_index, 1)?[0];
+
+        self.execution_index += 1;
+        IntType::from_byte_identifier(byte).ok_or(TrapCause::UnrecognizedIntType(byte))
+    }
+
+    fn binary_arithmetic(&mut self, operation: BinaryOperation) -> Result<(), TrapCause> {
+        let int_type = self.extract_int_type_skip()?;
+        let dest = self.extract_ptr_skip()?;
+        let lhs = self.handle_read_op_skip()?;
+        let rhs = self.handle_read_op_skip()?;
+
+        let result = arithmetic::binary(
+            operation,
+            int_type,
+            &self.mem_pool[lhs],
+            &self.mem_pool[rhs],
+        )?;
+        self.write_memory(dest, &result)
+    }
+
+    fn comparison(&mut self, comparison: Comparison) -> Result<(), TrapCause> {
+        let int_type = self.extract_int_type_skip()?;
+        let dest = self.extract_ptr_skip()?;
+        let lhs = self.handle_read_op_skip()?;
+        let rhs = self.handle_read_op_skip()?;
+
+        let result = arithmetic::compare(
+            comparison,
+            int_type,
+            &self.mem_pool[lhs],
+            &self.mem_pool[rhs],
+        )?;
+        self.write_memory(dest, &result)
+    }
+
+    /// Extract the target pointer and condition of a conditional jump, then jump to the target if
+    /// the condition is equal to `expected`.
+    fn conditional_jump(&mut self, expected: bool) -> Result<(), TrapCause> {
+        let ptr = self.extract_ptr_skip()?;
+        let condition = self.handle_read_op_skip()?;
+
+        if self.mem_pool[condition].iter().any(|b| *b != 0) == expected {
+            self.execution_index = ptr;
+        }
+        Ok(())
+    }
+
+    /// Get the stack entry at the given depth, where 0 is the top of the stack.
+    ///
+    /// Returns [TrapCause::StackUnderflow] if the stack is not deep enough.
+    fn stack_entry(&self, depth: usize) -> Result<&[u8], TrapCause> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .and_then(|index| self.stack.get(index))
+            .ok_or(TrapCause::StackUnderflow)
+    }
+
+    /// Execute the instruction whose opcode was just read.
+    ///
+    /// Returns [Flow::Halt] if execution should stop.
+    fn execute_instruction<T>(
+        &mut self,
+        opcode: u8,
+        env: &mut ExecutionEnvironment<T>,
+    ) -> Result<Flow, TrapCause>
+    where
+        T: Tracer,
+    {
+        match opcode {
+            byte_id::I_JUMP => self.execution_index = self.extract_ptr()?,
+
+            byte_id::I_MUTATE => {
+                let dest = self.extract_ptr_skip()?;
+                let data = self.handle_read_op_skip()?;
+
+                self.copy_memory(data, dest)?;
+            }
+
+            byte_id::I_PUSH => {
+                let data = self.handle_read_op_skip()?;
+                self.stack.push(&self.mem_pool[data]);
+            }
+
+            byte_id::I_EXTERN_CALL => {
+                let ptr = self.extract_ptr_skip()?;
+                env.call_extern(ptr, self)?;
+
+                if self.exit_code.is_some() {
+                    return Ok(Flow::Halt);
+                }
+            }
+
+            byte_id::I_CALL => {
+                let ptr = self.extract_ptr_skip()?;
+                self.call_stack.push(self.execution_index);
+                self.execution_index = ptr;
+            }
+
+            byte_id::I_RETURN => match self.call_stack.pop() {
+                Some(caller) => self.execution_index = caller,
+                None => return Ok(Flow::Halt),
+            },
+
+            byte_id::I_LOAD_A => {
+                let data = self.handle_read_op_skip()?;
+                env.ctx.ext_a = Some(data);
+            }
+
+            byte_id::I_ADD => self.binary_arithmetic(BinaryOperation::Add)?,
+            byte_id::I_SUB => self.binary_arithmetic(BinaryOperation::Sub)?,
+            byte_id::I_MUL => self.binary_arithmetic(BinaryOperation::Mul)?,
+            byte_id::I_DIV => self.binary_arithmetic(BinaryOperation::Div)?,
+            byte_id::I_REM => self.binary_arithmetic(BinaryOperation::Rem)?,
+
+            byte_id::I_NEG => {
+                let int_type = self.extract_int_type_skip()?;
+                let dest = self.extract_ptr_skip()?;
+                let data = self.handle_read_op_skip()?;
+
+                let result = arithmetic::negate(int_type, &self.mem_pool[data])?;
+                self.write_memory(dest, &result)?;
+            }
+
+            byte_id::I_EQ => self.comparison(Comparison::Eq)?,
+            byte_id::I_NE => self.comparison(Comparison::Ne)?,
+            byte_id::I_LT => self.comparison(Comparison::Lt)?,
+            byte_id::I_LE => self.comparison(Comparison::Le)?,
+            byte_id::I_GT => self.comparison(Comparison::Gt)?,
+            byte_id::I_GE => self.comparison(Comparison::Ge)?,
+
+            byte_id::I_JUMP_IF => self.conditional_jump(true)?,
+            byte_id::I_JUMP_IF_NOT => self.conditional_jump(false)?,
+
+            byte_id::I_POP => {
+                let dest = self.extract_ptr_skip()?;
+                let entry = self.stack_entry(0)?.to_vec();
+
+                self.write_memory(dest, &entry)?;
+                self.stack.pop();
+            }
+
+            byte_id::I_DROP => {
+                self.stack.pop().ok_or(TrapCause::StackUnderflow)?;
+            }
+
+            byte_id::I_DUP => {
+                let entry = self.stack_entry(0)?.to_vec();
+                self.stack.push(&entry);
+            }
+
+            byte_id::I_SWAP => {
+                let lower = self.stack_entry(1)?.to_vec();
+                let top = self.stack.pop().unwrap();
+
+                self.stack.pop();
+                self.stack.push(&top);
+                self.stack.push(&lower);
+            }
+
+            byte_id::I_OVER => {
+                let entry = self.stack_entry(1)?.to_vec();
+                self.stack.push(&entry);
+            }
+
+            _ => return Err(TrapCause::UnrecognizedInstruction),
+        }
+        Ok(Flow::Continue)
     }
 
     /// Starts or resumes execution at the current execution index.
     ///
-    /// If the execution index is greater than the length of the memory pool, this function will
-    /// return immediately.
+    /// Returns [ExecutionStatus::Completed] once execution halts. If the execution index is greater
+    /// than the length of the memory pool, this function will return immediately.
+    ///
+    /// Returns [ExecutionStatus::Breakpoint] if execution reaches one of the breakpoints, including
+    /// the one at the current execution index. Calling this function again executes the
+    /// instruction at the reported breakpoint, then continues as usual.
+    ///
+    /// If an instruction traps, execution stops and the execution index is reset to the start of
+    /// the failing instruction.
     ///
     /// # Examples
     /// ```
@@ -189,7 +779,7 @@ impl VmInstance {
     /// use ivm_vm::ivm_ext_x32::IvmX32ExternMap;
     /// use ivm_vm::{EmptyExternMap, ExecutionEnvironment, VmInstance};
     ///
-    /// let mut extern_map = IvmX32ExternMap;
+    /// let mut extern_map = IvmX32ExternMap::new();
     /// let mut env = ExecutionEnvironment::new(&mut extern_map);
     ///
     /// let mut vm = VmInstance::new(
@@ -199,58 +789,216 @@ impl VmInstance {
     /// );
     ///
     /// // Nothing will be happen.
-    /// vm.continue_execution(&mut env);
+    /// vm.continue_execution(&mut env).unwrap();
     /// ```
-    pub fn continue_execution(&mut self, env: &mut ExecutionEnvironment) {
-        while self.execution_index < self.mem_pool.len() {
-            let byte_instruction = self.mem_pool[self.execution_index];
+    pub fn continue_execution<T>(
+        &mut self,
+        env: &mut ExecutionEnvironment<T>,
+    ) -> Result<ExecutionStatus, VmError>
+    where
+        T: Tracer,
+    {
+        self.run(env, None)
+    }
 
-            self.execution_index += 1;
+    /// Run the program until it ends, ignoring breakpoints.
+    ///
+    /// # Examples
+    /// ```
+    /// use ivm_compile::{Instruction, ReadOperation};
+    /// use ivm_compile::options::ProgramOptions;
+    /// use ivm_vm::ivm_ext_x32::IvmX32ExternMap;
+    /// use ivm_vm::{ExecutionEnvironment, ExitStatus, ivm_ext_x32, VmInstance};
+    ///
+    /// let mut extern_map = IvmX32ExternMap::new();
+    /// let mut env = ExecutionEnvironment::new(&mut extern_map);
+    ///
+    /// let mut vm = VmInstance::reserve_ivm_ext_x32(ProgramOptions::default());
+    /// vm.introduce(ivm_compile::compile_all(
+    ///     [
+    ///         Instruction::LoadA(ReadOperation::Local(3i32.to_le_bytes().to_vec())),
+    ///         Instruction::ExternCall(ivm_ext_x32::EXTC_EXIT),
+    ///     ],
+    ///     &vm.options,
+    /// ));
+    ///
+    /// let status = vm.run_to_exit(&mut env);
+    /// assert_eq!(status, ExitStatus::Code(3));
+    /// assert_eq!(status.code(), 3);
+    /// ```
+    pub fn run_to_exit<T>(&mut self, env: &mut ExecutionEnvironment<T>) -> ExitStatus
+    where
+        T: Tracer,
+    {
+        loop {
+            match self.continue_execution(env) {
+                Ok(ExecutionStatus::Breakpoint(_)) => continue,
+                Ok(ExecutionStatus::Exited(code)) => return ExitStatus::Code(code),
+                Err(err) => return ExitStatus::Trapped(err),
+                Ok(ExecutionStatus::Completed | ExecutionStatus::OutOfFuel) => {
+                    return ExitStatus::Normal
+                }
+            }
+        }
+    }
 
-            match byte_instruction {
-                byte_id::I_JUMP => self.execution_index = self.extract_ptr(),
+    /// Starts or resumes execution at the current execution index, executing at most the given
+    /// amount of instructions.
+    ///
+    /// This allows running untrusted programs, which may never halt, without blocking the host.
+    /// If the budget is used up, [ExecutionStatus::OutOfFuel] is returned and a later call will
+    /// resume execution where it stopped.
+    ///
+    /// Breakpoints and traps are handled the same way as in [Self::continue_execution()].
+    ///
+    /// # Examples
+    /// ```
+    /// use ivm_compile::Instruction;
+    /// use ivm_compile::options::ProgramOptions;
+    /// use ivm_vm::ivm_ext_x32::IvmX32ExternMap;
+    /// use ivm_vm::{ExecutionEnvironment, ExecutionStatus, ivm_ext_x32, VmInstance};
+    ///
+    /// let mut extern_map = IvmX32ExternMap::new();
+    /// let mut env = ExecutionEnvironment::new(&mut extern_map);
+    ///
+    /// let mut vm = VmInstance::reserve_ivm_ext_x32(ProgramOptions::default());
+    /// vm.introduce(ivm_compile::compile_all(
+    ///     [Instruction::Jump(ivm_ext_x32::REGISTER_RESERVED)],
+    ///     &vm.options,
+    /// ));
+    ///
+    /// // This program loops forever.
+    /// assert_eq!(vm.run_for(&mut env, 1000), Ok(ExecutionStatus::OutOfFuel));
+    /// ```
+    pub fn run_for<T>(
+        &mut self,
+        env: &mut ExecutionEnvironment<T>,
+        steps: u64,
+    ) -> Result<ExecutionStatus, VmError>
+    where
+        T: Tracer,
+    {
+        self.run(env, Some(steps))
+    }
 
-                byte_id::I_MUTATE => {
-                    let dest = self.extract_ptr_skip();
-                    let data = self.handle_read_op_skip();
+    /// Execute exactly one instruction at the current execution index, ignoring breakpoints.
+    ///
+    /// Returns `None` if the execution index is not within the memory pool, in which case nothing
+    /// is executed.
+    pub fn step<T>(&mut self, env: &mut ExecutionEnvironment<T>) -> Result<Option<Step>, VmError>
+    where
+        T: Tracer,
+    {
+        let start = self.execution_index;
+        let (flow, instruction) = self.step_instruction(env, true)?;
 
-                    unsafe {
-                        self.mem_pool[dest..][..data.len()].copy_from_slice(&*data);
-                    }
-                }
+        Ok(
+            instruction
+                .map(|instruction| Step::new(start, instruction, matches!(flow, Flow::Halt))),
+        )
+    }
 
-                byte_id::I_PUSH => {
-                    let data = self.handle_read_op_skip();
-                    self.stack.push(data);
-                }
+    /// Run until execution halts, reaches a breakpoint, or the fuel is used up.
+    fn run<T>(
+        &mut self,
+        env: &mut ExecutionEnvironment<T>,
+        fuel: Option<u64>,
+    ) -> Result<ExecutionStatus, VmError>
+    where
+        T: Tracer,
+    {
+        let mut executed = 0;
 
-                byte_id::I_EXTERN_CALL => {
-                    let ptr = self.extract_ptr_skip();
-                    env.call_extern(ptr, self);
-                }
+        loop {
+            if fuel.is_some_and(|fuel| executed >= fuel) {
+                return Ok(if self.execution_index < self.mem_pool.len() {
+                    ExecutionStatus::OutOfFuel
+                } else {
+                    ExecutionStatus::Completed
+                });
+            }
 
-                byte_id::I_CALL => {
-                    let ptr = self.extract_ptr_skip();
-                    self.call_stack.push(self.execution_index);
-                    self.execution_index = ptr;
-                }
+            // Only the first instruction of a run may resume past the breakpoint it paused at.
+            let resumed = self.resume_breakpoint.take() == Some(self.execution_index);
 
-                byte_id::I_RETURN => match self.call_stack.pop() {
-                    Some(caller) => self.execution_index = caller,
-                    None => return,
-                },
+            if !resumed && self.breakpoints.contains(&self.execution_index) {
+                self.resume_breakpoint = Some(self.execution_index);
+                return Ok(ExecutionStatus::Breakpoint(self.execution_index));
+            }
+
+            if let (Flow::Halt, _) = self.step_instruction(env, false)? {
+                return Ok(match self.exit_code {
+                    Some(code) => ExecutionStatus::Exited(code),
+                    None => ExecutionStatus::Completed,
+                });
+            }
+            executed += 1;
+        }
+    }
 
-                byte_id::I_LOAD_A => {
-                    let data = self.handle_read_op_skip();
-                    env.ctx.ext_a = data;
+    /// Decode the instruction at the given index, reporting malformed instructions as traps.
+    fn decode_instruction(&self, index: usize) -> Result<Instruction, TrapCause> {
+        Instruction::decode(&self.mem_pool, index, &self.options)
+            .map(|(instruction, _)| instruction)
+            .map_err(|err| match *err.cause() {
+                DecodeErrorCause::UnexpectedEnd => TrapCause::OutOfBounds {
+                    index: err.offset(),
+                    len: 1,
+                },
+                DecodeErrorCause::UnrecognizedInstruction(_) => TrapCause::UnrecognizedInstruction,
+                DecodeErrorCause::UnrecognizedReadOperation(id) => {
+                    TrapCause::UnrecognizedReadOperation(id)
                 }
+                DecodeErrorCause::UnrecognizedIntType(id) => TrapCause::UnrecognizedIntType(id),
+            })
+    }
 
-                _ => panic!(
-                    "unrecognized instruction (hex): {byte_instruction:02x} at execution index {}",
-                    self.execution_index - 1
-                ),
-            }
+    /// Execute the instruction at the current execution index.
+    ///
+    /// Returns [Flow::Halt] if the execution index is not within the memory pool, if the program
+    /// exited, or if the instruction halted execution.
+    ///
+    /// If `decode` is true, the instruction is also decoded and returned. It is decoded before
+    /// being executed, as it may overwrite itself, so a malformed instruction traps without any
+    /// effect. Nothing is returned if no instruction was executed.
+    fn step_instruction<T>(
+        &mut self,
+        env: &mut ExecutionEnvironment<T>,
+        decode: bool,
+    ) -> Result<(Flow, Option<Instruction>), VmError>
+    where
+        T: Tracer,
+    {
+        let start = self.execution_index;
+
+        let Some(opcode) = self.mem_pool.get(start).copied() else {
+            return Ok((Flow::Halt, None));
+        };
+
+        if self.exit_code.is_some() {
+            return Ok((Flow::Halt, None));
         }
+
+        env.tracer.before_instruction(self, start, opcode);
+
+        let decoded = match decode {
+            true => self.decode_instruction(start).map(Some),
+            false => Ok(None),
+        };
+
+        let result = decoded.and_then(|instruction| {
+            self.execution_index += 1;
+            self.execute_instruction(opcode, env)
+                .map(|flow| (flow, instruction))
+        });
+
+        if result.is_err() {
+            self.execution_index = start;
+        }
+
+        env.tracer
+            .after_instruction(self, start, opcode, result.as_ref().err());
+        result.map_err(|cause| VmError::new(start, opcode, cause))
     }
 
     /// Create a new VmInstance.
@@ -265,6 +1013,12 @@ impl VmInstance {
             execution_index: ptr_index,
             stack: Stack::with_capacity(3),
             call_stack: Vec::new(),
+            memory_policy: MemoryPolicy::Fixed,
+            breakpoints: HashSet::new(),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            exit_code: None,
+            resume_breakpoint: None,
         }
     }
 
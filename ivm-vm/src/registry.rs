@@ -0,0 +1,191 @@
+//! Composing extern maps from closures and other extern maps.
+//!
+//! # Example
+//!
+//! ```
+//! use ivm_vm::ivm_ext_x32::{self, IvmX32ExternMap};
+//! use ivm_vm::registry::ExternRegistry;
+//!
+//! let mut x32 = IvmX32ExternMap::new();
+//! let mut registry = ExternRegistry::new();
+//!
+//! registry
+//!     .register_map(0..1000, &mut x32)
+//!     .unwrap()
+//!     .register(1000, |_ctx, vm| {
+//!         vm.exit(0);
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//!
+//! // Extern call ids may only be registered once.
+//! assert!(registry.register(ivm_ext_x32::EXTC_EXIT, |_, _| Ok(())).is_err());
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use crate::trap::TrapCause;
+use crate::{ExecutionContext, ExternMap, ExternResult, VmInstance};
+
+/// A closure handling a single extern call id.
+pub type ExternFn<'a> = Box<dyn FnMut(&mut ExecutionContext, &mut VmInstance) -> ExternResult + 'a>;
+
+/// A closure handling extern calls no other handler of an [ExternRegistry] recognizes.
+pub type FallbackFn<'a> =
+    Box<dyn FnMut(&mut ExecutionContext, usize, &mut VmInstance) -> ExternResult + 'a>;
+
+/// An error returned when a handler could not be registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    /// The range of extern call ids is empty.
+    EmptyRange(Range<usize>),
+
+    /// The range of extern call ids overlaps a range which was already registered.
+    Collision {
+        range: Range<usize>,
+        existing: Range<usize>,
+    },
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyRange(range) => write!(f, "extern call id range {range:?} is empty"),
+            Self::Collision { range, existing } => write!(
+                f,
+                "extern call id range {range:?} overlaps registered range {existing:?}"
+            ),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+enum Handler<'a> {
+    Fn(ExternFn<'a>),
+    Map(&'a mut dyn ExternMap),
+}
+
+/// An extern map dispatching extern calls to the handlers registered for their ids.
+///
+/// Handlers are either closures registered for a single extern call id, or extern maps registered
+/// for a range of extern call ids. Extern maps receive the original extern call id.
+///
+/// Extern calls without a handler are passed to the fallback, which traps with
+/// [TrapCause::UnrecognizedExtern] by default.
+#[derive(Default)]
+pub struct ExternRegistry<'a> {
+    ranges: BTreeMap<usize, (usize, usize)>,
+    handlers: Vec<Handler<'a>>,
+    fallback: Option<FallbackFn<'a>>,
+}
+
+impl<'a> ExternRegistry<'a> {
+    /// Get the index of the handler registered for the given extern call id.
+    fn lookup(&self, call_id: usize) -> Option<usize> {
+        self.ranges
+            .range(..=call_id)
+            .next_back()
+            .filter(|(_, (end, _))| call_id < *end)
+            .map(|(_, (_, handler))| *handler)
+    }
+
+    fn insert(
+        &mut self,
+        range: Range<usize>,
+        handler: Handler<'a>,
+    ) -> Result<&mut Self, RegistryError> {
+        if range.is_empty() {
+            return Err(RegistryError::EmptyRange(range));
+        }
+
+        // Report the first overlapping range, which either starts before the new range and ends
+        // within it, or starts within it.
+        let overlapping = self
+            .ranges
+            .range(..range.start)
+            .next_back()
+            .filter(|(_, (end, _))| *end > range.start)
+            .or_else(|| self.ranges.range(range.clone()).next());
+
+        if let Some((start, (end, _))) = overlapping {
+            return Err(RegistryError::Collision {
+                existing: *start..*end,
+                range,
+            });
+        }
+
+        self.ranges
+            .insert(range.start, (range.end, self.handlers.len()));
+        self.handlers.push(handler);
+        Ok(self)
+    }
+
+    /// Returns true if a handler is registered for the given extern call id.
+    #[inline]
+    pub fn contains(&self, call_id: usize) -> bool {
+        self.lookup(call_id).is_some()
+    }
+
+    /// Register a closure handling the given extern call id.
+    ///
+    /// Returns [RegistryError::Collision] if the id was already registered.
+    pub fn register<F>(&mut self, call_id: usize, handler: F) -> Result<&mut Self, RegistryError>
+    where
+        F: FnMut(&mut ExecutionContext, &mut VmInstance) -> ExternResult + 'a,
+    {
+        self.insert(
+            call_id..call_id.saturating_add(1),
+            Handler::Fn(Box::new(handler)),
+        )
+    }
+
+    /// Register an extern map handling the given range of extern call ids.
+    ///
+    /// Returns [RegistryError::Collision] if any id of the range was already registered.
+    pub fn register_map(
+        &mut self,
+        call_ids: Range<usize>,
+        map: &'a mut dyn ExternMap,
+    ) -> Result<&mut Self, RegistryError> {
+        self.insert(call_ids, Handler::Map(map))
+    }
+
+    /// Set the closure handling extern calls without a registered handler.
+    pub fn set_fallback<F>(&mut self, fallback: F) -> &mut Self
+    where
+        F: FnMut(&mut ExecutionContext, usize, &mut VmInstance) -> ExternResult + 'a,
+    {
+        self.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// Create a new, empty ExternRegistry.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ExternMap for ExternRegistry<'_> {
+    fn handle(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        call_id: usize,
+        vm: &mut VmInstance,
+    ) -> ExternResult {
+        match self.lookup(call_id) {
+            Some(handler) => match &mut self.handlers[handler] {
+                Handler::Fn(handler) => handler(ctx, vm),
+                Handler::Map(map) => map.handle(ctx, call_id, vm),
+            },
+            None => match &mut self.fallback {
+                Some(fallback) => fallback(ctx, call_id, vm),
+                None => Err(TrapCause::UnrecognizedExtern(call_id)),
+            },
+        }
+    }
+}
@@ -1,21 +1,118 @@
-use crate::{ExecutionContext, ExternMap, VmInstance};
+use std::collections::{HashMap, VecDeque};
+
+use crate::ivm_ext_x32;
+use crate::trap::TrapCause;
+use crate::{ExecutionContext, ExternMap, ExternResult, VmInstance};
 
 #[derive(Clone)]
 pub enum IllegalOperationHandleMethod {
     Panic,
     SilentFail,
+
+    /// Trap with [TrapCause::ExternDenied].
+    Trap,
+
+    /// Write the given error code to the [ivm_ext_x32::REG_ERROR] register, then continue.
+    WriteError(i32),
+}
+
+/// Limits on how often an extern call may be made.
+///
+/// Bytes are counted by the [ByteCounter] of the [GuardedExternMap]. Calls with a byte quota are
+/// denied if their amount of bytes is not known.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallQuota {
+    pub max_calls: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+/// The reason an extern call was denied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denial {
+    /// The call matched the guards.
+    Guard,
+
+    /// The call would exceed [CallQuota::max_calls].
+    CallQuota,
+
+    /// The call would exceed [CallQuota::max_bytes].
+    ByteQuota,
+
+    /// The hook denied the call.
+    Hook,
+}
+
+/// An entry of the audit log of a [GuardedExternMap].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    call_id: usize,
+    execution_index: usize,
+    denial: Option<Denial>,
+}
+
+impl AuditEntry {
+    /// Get the extern call id.
+    #[inline]
+    pub const fn call_id(&self) -> usize {
+        self.call_id
+    }
+
+    /// Get the execution index of the VM at the time of the call.
+    ///
+    /// This is the index right after the extern call instruction.
+    #[inline]
+    pub const fn execution_index(&self) -> usize {
+        self.execution_index
+    }
+
+    /// Get the reason the call was denied, or `None` if it was allowed.
+    #[inline]
+    pub const fn denial(&self) -> Option<Denial> {
+        self.denial
+    }
+
+    #[inline]
+    pub const fn new(call_id: usize, execution_index: usize, denial: Option<Denial>) -> Self {
+        Self {
+            call_id,
+            execution_index,
+            denial,
+        }
+    }
 }
 
+/// A hook deciding whether an extern call is allowed, after the guards and quotas allowed it.
+pub type GuardHook<'a> = Box<dyn FnMut(&ExecutionContext, usize, &VmInstance) -> bool + 'a>;
+
+/// Get the amount of arguments an extern call pops from the stack, and the amount of [u32] results
+/// it pushes back, or `None` if the extern call is not known.
+///
+/// See [ivm_ext_x32::stack_effect()].
+pub type StackEffect = fn(usize) -> Option<(usize, usize)>;
+
+/// Get the amount of bytes an extern call writes, without consuming its arguments, or `None` if
+/// the amount is not known.
+///
+/// See [ivm_ext_x32::written_len()].
+pub type ByteCounter = fn(&ExecutionContext, usize, &VmInstance) -> Option<u64>;
+
 /// An extern map which checks incoming calls, ensuring that they do not match any of the guards
 /// contained.
 ///
 /// If one of these guards match the incoming call, this GuardedExternMap will act according to the
-/// inner [IllegalOperationHandleMethod].
+/// inner [IllegalOperationHandleMethod]. The same applies to calls exceeding their [CallQuota], or
+/// denied by the hook.
 pub struct GuardedExternMap<'a> {
     inner: &'a mut dyn ExternMap,
     guards: Vec<usize>,
     inverted: bool,
     handle_method: IllegalOperationHandleMethod,
+    quotas: HashMap<usize, (CallQuota, u64, u64)>,
+    hook: Option<GuardHook<'a>>,
+    stack_effect: Option<StackEffect>,
+    byte_counter: Option<ByteCounter>,
+    audit_log: VecDeque<AuditEntry>,
+    audit_capacity: usize,
 }
 
 impl<'a> GuardedExternMap<'a> {
@@ -23,6 +120,131 @@ impl<'a> GuardedExternMap<'a> {
         self.guards.push(guard);
     }
 
+    /// Limit how often the given extern call may be made, resetting its usage.
+    pub fn set_quota(&mut self, call_id: usize, quota: CallQuota) {
+        self.quotas.insert(call_id, (quota, 0, 0));
+    }
+
+    /// Set the hook deciding whether a call is allowed.
+    ///
+    /// The hook is only invoked for calls allowed by the guards and quotas.
+    pub fn set_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&ExecutionContext, usize, &VmInstance) -> bool + 'a,
+    {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Set how the bytes written by the extern calls of the inner extern map are counted against
+    /// [CallQuota::max_bytes].
+    ///
+    /// While no byte counter is set, every call with a byte quota is denied.
+    pub fn set_byte_counter(&mut self, byte_counter: ByteCounter) {
+        self.byte_counter = Some(byte_counter);
+    }
+
+    /// Set how the extern calls of the inner extern map use the stack.
+    ///
+    /// Calls denied under the stack calling convention then pop their arguments, and push `0u32`
+    /// for every result, like a failing call would. Calls not known to the given function, or
+    /// denied while no stack effect is set, leave the stack untouched.
+    pub fn set_stack_effect(&mut self, stack_effect: StackEffect) {
+        self.stack_effect = Some(stack_effect);
+    }
+
+    /// Pop the arguments of a denied call and push its results.
+    ///
+    /// See [Self::set_stack_effect()].
+    ///
+    /// Returns [TrapCause::StackUnderflow] if the stack does not hold every argument.
+    fn discard_args(
+        &self,
+        ctx: &ExecutionContext,
+        call_id: usize,
+        vm: &mut VmInstance,
+    ) -> ExternResult {
+        let Some((pops, pushes)) = self.stack_effect.and_then(|effect| effect(call_id)) else {
+            return Ok(());
+        };
+
+        if !ctx.uses_stack() {
+            return Ok(());
+        }
+
+        if vm.stack.len() < pops {
+            return Err(TrapCause::StackUnderflow);
+        }
+
+        for _ in 0..pops {
+            vm.stack.pop();
+        }
+        for _ in 0..pushes {
+            ctx.push_u32(vm, 0);
+        }
+        Ok(())
+    }
+
+    /// Start recording every allowed and denied call, keeping at most `capacity` entries.
+    ///
+    /// Once the audit log is full, the oldest entry is dropped for every new one.
+    pub fn enable_audit_log(&mut self, capacity: usize) {
+        while self.audit_log.len() > capacity {
+            self.audit_log.pop_front();
+        }
+        self.audit_capacity = capacity;
+    }
+
+    /// Stop recording calls, discarding the audit log.
+    pub fn disable_audit_log(&mut self) {
+        self.audit_log.clear();
+        self.audit_capacity = 0;
+    }
+
+    /// Get the recorded calls, oldest first.
+    ///
+    /// Yields nothing if the audit log is not enabled.
+    pub fn audit_log(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &AuditEntry> + ExactSizeIterator + '_ {
+        self.audit_log.iter()
+    }
+
+    /// Decide whether the call is allowed, updating its quota usage if it is.
+    fn check(&mut self, ctx: &ExecutionContext, call_id: usize, vm: &VmInstance) -> Option<Denial> {
+        if self.guards.contains(&call_id) ^ self.inverted {
+            return Some(Denial::Guard);
+        }
+
+        let mut len = 0;
+
+        if let Some((quota, calls, bytes)) = self.quotas.get(&call_id) {
+            if quota.max_calls.is_some_and(|max| *calls >= max) {
+                return Some(Denial::CallQuota);
+            }
+
+            if let Some(max) = quota.max_bytes {
+                // Calls of unknown size are denied, rather than exempt from the byte quota.
+                len = match self.byte_counter.and_then(|count| count(ctx, call_id, vm)) {
+                    Some(len) if *bytes + len <= max => len,
+                    _ => return Some(Denial::ByteQuota),
+                };
+            }
+        }
+
+        if let Some(hook) = &mut self.hook {
+            if !hook(ctx, call_id, vm) {
+                return Some(Denial::Hook);
+            }
+        }
+
+        // Only allowed calls use up their quota.
+        if let Some((_, calls, bytes)) = self.quotas.get_mut(&call_id) {
+            *calls += 1;
+            *bytes += len;
+        }
+        None
+    }
+
     pub fn new(
         inner: &'a mut dyn ExternMap,
         guards: Vec<usize>,
@@ -34,6 +256,12 @@ impl<'a> GuardedExternMap<'a> {
             guards,
             inverted,
             handle_method,
+            quotas: HashMap::new(),
+            hook: None,
+            stack_effect: None,
+            byte_counter: None,
+            audit_log: VecDeque::new(),
+            audit_capacity: 0,
         }
     }
 
@@ -47,10 +275,24 @@ impl<'a> GuardedExternMap<'a> {
 }
 
 impl ExternMap for GuardedExternMap<'_> {
-    fn handle(&mut self, ctx: &mut ExecutionContext, call_id: usize, vm: &mut VmInstance) {
-        if !self.guards.contains(&call_id) ^ self.inverted {
-            self.inner.handle(ctx, call_id, vm);
-            return;
+    fn handle(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        call_id: usize,
+        vm: &mut VmInstance,
+    ) -> ExternResult {
+        let denial = self.check(ctx, call_id, vm);
+
+        if self.audit_capacity > 0 {
+            if self.audit_log.len() == self.audit_capacity {
+                self.audit_log.pop_front();
+            }
+            self.audit_log
+                .push_back(AuditEntry::new(call_id, vm.execution_index, denial));
+        }
+
+        if denial.is_none() {
+            return self.inner.handle(ctx, call_id, vm);
         }
 
         match self.handle_method {
@@ -60,7 +302,12 @@ impl ExternMap for GuardedExternMap<'_> {
                     vm.execution_index
                 )
             }
-            IllegalOperationHandleMethod::SilentFail => (),
+            IllegalOperationHandleMethod::SilentFail => self.discard_args(ctx, call_id, vm),
+            IllegalOperationHandleMethod::Trap => Err(TrapCause::ExternDenied(call_id)),
+            IllegalOperationHandleMethod::WriteError(code) => {
+                self.discard_args(ctx, call_id, vm)?;
+                ivm_ext_x32::write_err_register(ctx, &mut vm.mem_pool, code)
+            }
         }
     }
 }
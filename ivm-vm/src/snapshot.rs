@@ -0,0 +1,394 @@
+//! Saving and restoring the full state of a [VmInstance] and its [ExecutionContext].
+//!
+//! Snapshots allow checkpointing long-running programs, or migrating them between hosts. Restoring
+//! a snapshot results in an identical VM, which resumes execution where the original stopped.
+//!
+//! # Format
+//!
+//! Every integer is stored in little endian byte order. Lengths, counts and indexes are stored as
+//! `u64`, regardless of the [ivm_compile::options::MemoryPointerLength] of the program.
+//!
+//! ```text
+//! Magic: b"IVMS"
+//! Version: u16
+//!
+//! Cfv: u32
+//! MemoryPointerLength: u8
+//! MemoryPolicy: u8 (0 = Fixed, 1 = Grow), followed by the limit: u64 if Grow
+//! ExecutionIndex: u64
+//! MemoryPool: u64 length, followed by the bytes
+//! Stack: u64 count, followed by each entry from bottom to top as a u64 length and the bytes
+//! CallStack: u64 count, followed by each index: u64
+//! Breakpoints: u64 count, followed by each index: u64 in ascending order
+//! ResumeBreakpoint: u8 (0 = none, 1 = paused), followed by the index: u64 if paused
+//!
+//! ExtA: u8 (0 = not loaded, 1 = loaded), followed by the start and end: u64 if loaded
+//! Ext1: u8
+//! Heap: the live allocations and the freed blocks, each as a u64 count followed by each
+//!       address: u64 and length: u64 in ascending address order
+//!
+//! Args: u64 count, followed by each argument as a u64 length and the UTF-8 bytes
+//! Env: u64 count, followed by each name and value as a u64 length and the UTF-8 bytes, in
+//!      ascending name order
+//! ExitCode: u8 (0 = running, 1 = exited), followed by the code: i32 if exited
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use ivm_compile::options::{MemoryPointerLength, ProgramOptions};
+
+use crate::heap::Heap;
+use crate::stack::Stack;
+use crate::{ExecutionContext, MemoryPolicy, VmInstance};
+
+/// The magic bytes every snapshot starts with.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"IVMS";
+
+/// The snapshot format version written by [VmInstance::snapshot()].
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+/// An error returned when a snapshot could not be restored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The snapshot does not start with [SNAPSHOT_MAGIC].
+    InvalidMagic,
+
+    /// The snapshot format version is not supported.
+    UnsupportedVersion(u16),
+
+    /// The snapshot ended in the middle of a value.
+    UnexpectedEnd,
+
+    /// A value of the snapshot was not recognized.
+    ///
+    /// Contains the offset of the value.
+    InvalidValue(usize),
+
+    /// The snapshot contains bytes after its last value.
+    TrailingBytes,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not a snapshot"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            Self::UnexpectedEnd => write!(f, "unexpected end of snapshot"),
+            Self::InvalidValue(offset) => write!(f, "invalid value at offset {offset}"),
+            Self::TrailingBytes => write!(f, "trailing bytes after snapshot"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+fn write_u64(dest: &mut Vec<u8>, value: usize) {
+    dest.extend((value as u64).to_le_bytes());
+}
+
+fn write_blocks<I>(dest: &mut Vec<u8>, blocks: I)
+where
+    I: ExactSizeIterator<Item = (usize, usize)>,
+{
+    write_u64(dest, blocks.len());
+
+    for (address, len) in blocks {
+        write_u64(dest, address);
+        write_u64(dest, len);
+    }
+}
+
+fn write_bytes(dest: &mut Vec<u8>, bytes: &[u8]) {
+    write_u64(dest, bytes.len());
+    dest.extend_from_slice(bytes);
+}
+
+/// Returns true if every heap block is non-empty, inside the memory pool, and does not overlap any
+/// other block.
+fn valid_blocks<'a, I>(mem_len: usize, blocks: I) -> bool
+where
+    I: IntoIterator<Item = &'a (usize, usize)>,
+{
+    let mut blocks = blocks.into_iter().copied().collect::<Vec<_>>();
+    blocks.sort_unstable();
+
+    let mut end = 0;
+
+    for (address, len) in blocks {
+        match address.checked_add(len) {
+            Some(block_end) if len > 0 && address >= end && block_end <= mem_len => end = block_end,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// A cursor over a snapshot.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..)
+            .and_then(|bytes| bytes.get(..len))
+            .ok_or(SnapshotError::UnexpectedEnd)?;
+
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<usize, SnapshotError> {
+        let offset = self.offset;
+        let value = u64::from_le_bytes(self.array()?);
+
+        usize::try_from(value).map_err(|_| SnapshotError::InvalidValue(offset))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u64()?;
+        self.take(len)
+    }
+
+    /// Read a flag byte, which must be either 0 or 1.
+    fn flag(&mut self) -> Result<bool, SnapshotError> {
+        let offset = self.offset;
+
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidValue(offset)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let offset = self.offset;
+
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| SnapshotError::InvalidValue(offset))
+    }
+
+    fn block(&mut self) -> Result<(usize, usize), SnapshotError> {
+        Ok((self.u64()?, self.u64()?))
+    }
+
+    /// Read a count, followed by that many values.
+    fn repeat<T, F>(&mut self, mut read: F) -> Result<Vec<T>, SnapshotError>
+    where
+        F: FnMut(&mut Self) -> Result<T, SnapshotError>,
+    {
+        let count = self.u64()?;
+
+        // The count is not trusted for preallocation, as it may be corrupted.
+        let mut values = Vec::new();
+
+        for _ in 0..count {
+            values.push(read(self)?);
+        }
+        Ok(values)
+    }
+}
+
+impl VmInstance {
+    /// Write a snapshot of this VM and the given execution context.
+    ///
+    /// See the [module documentation](self) for the format.
+    pub fn snapshot(&self, ctx: &ExecutionContext) -> Vec<u8> {
+        let mut dest = Vec::with_capacity(self.mem_pool.len() + 64);
+
+        dest.extend(SNAPSHOT_MAGIC);
+        dest.extend(SNAPSHOT_VERSION.to_le_bytes());
+
+        dest.extend(self.options.cfv().to_le_bytes());
+        dest.push(self.options.ptr_len().get_byte_identifier());
+
+        match self.memory_policy {
+            MemoryPolicy::Fixed => dest.push(0),
+            MemoryPolicy::Grow(limit) => {
+                dest.push(1);
+                write_u64(&mut dest, limit);
+            }
+        }
+
+        write_u64(&mut dest, self.execution_index);
+        write_bytes(&mut dest, &self.mem_pool);
+
+        write_u64(&mut dest, self.stack.len());
+        self.stack
+            .iter()
+            .for_each(|entry| write_bytes(&mut dest, entry));
+
+        write_u64(&mut dest, self.call_stack.len());
+        self.call_stack
+            .iter()
+            .for_each(|index| write_u64(&mut dest, *index));
+
+        let mut breakpoints = self.breakpoints.iter().copied().collect::<Vec<_>>();
+        breakpoints.sort_unstable();
+
+        write_u64(&mut dest, breakpoints.len());
+        breakpoints
+            .into_iter()
+            .for_each(|index| write_u64(&mut dest, index));
+
+        match self.resume_breakpoint {
+            Some(index) => {
+                dest.push(1);
+                write_u64(&mut dest, index);
+            }
+            None => dest.push(0),
+        }
+
+        match &ctx.ext_a {
+            Some(range) => {
+                dest.push(1);
+                write_u64(&mut dest, range.start);
+                write_u64(&mut dest, range.end);
+            }
+            None => dest.push(0),
+        }
+        dest.push(ctx.ext_1 as u8);
+
+        write_blocks(&mut dest, ctx.heap.live());
+        write_blocks(&mut dest, ctx.heap.freed());
+
+        write_u64(&mut dest, self.args.len());
+        self.args
+            .iter()
+            .for_each(|arg| write_bytes(&mut dest, arg.as_bytes()));
+
+        write_u64(&mut dest, self.env.len());
+
+        for (key, value) in &self.env {
+            write_bytes(&mut dest, key.as_bytes());
+            write_bytes(&mut dest, value.as_bytes());
+        }
+
+        match self.exit_code {
+            Some(code) => {
+                dest.push(1);
+                dest.extend(code.to_le_bytes());
+            }
+            None => dest.push(0),
+        }
+
+        dest
+    }
+
+    /// Restore a VM and its execution context from a snapshot written by [Self::snapshot()].
+    ///
+    /// The restored execution context should be moved into the
+    /// [crate::ExecutionEnvironment] used to resume execution.
+    ///
+    /// Returns [SnapshotError::InvalidValue] if a heap block is empty, lies outside the memory
+    /// pool, or overlaps another heap block.
+    pub fn restore(snapshot: &[u8]) -> Result<(Self, ExecutionContext), SnapshotError> {
+        let mut reader = Reader {
+            bytes: snapshot,
+            offset: 0,
+        };
+
+        if reader.array()? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        let version = u16::from_le_bytes(reader.array()?);
+
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let cfv = u32::from_le_bytes(reader.array()?);
+
+        let ptr_len_offset = reader.offset;
+        let ptr_len = MemoryPointerLength::from_byte_identifier(reader.byte()?)
+            .ok_or(SnapshotError::InvalidValue(ptr_len_offset))?;
+
+        let memory_policy = match reader.flag()? {
+            false => MemoryPolicy::Fixed,
+            true => MemoryPolicy::Grow(reader.u64()?),
+        };
+
+        let execution_index = reader.u64()?;
+        let mem_pool = reader.bytes()?.to_vec();
+
+        let mut stack = Stack::new();
+
+        for entry in reader.repeat(Reader::bytes)? {
+            stack.push(entry);
+        }
+
+        let call_stack = reader.repeat(Reader::u64)?;
+        let breakpoints = reader
+            .repeat(Reader::u64)?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let resume_breakpoint = match reader.flag()? {
+            false => None,
+            true => Some(reader.u64()?),
+        };
+
+        let ext_a = match reader.flag()? {
+            false => None,
+            true => Some(reader.u64()?..reader.u64()?),
+        };
+        let ext_1 = reader.flag()?;
+
+        let blocks_offset = reader.offset;
+
+        let live = reader.repeat(Reader::block)?;
+        let freed = reader.repeat(Reader::block)?;
+
+        if !valid_blocks(mem_pool.len(), live.iter().chain(&freed)) {
+            return Err(SnapshotError::InvalidValue(blocks_offset));
+        }
+        let heap = Heap::from_parts(live, freed);
+
+        let args = reader.repeat(Reader::string)?;
+        let env = reader
+            .repeat(|reader| Ok((reader.string()?, reader.string()?)))?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+
+        let exit_code = match reader.flag()? {
+            false => None,
+            true => Some(i32::from_le_bytes(reader.array()?)),
+        };
+
+        if reader.offset != snapshot.len() {
+            return Err(SnapshotError::TrailingBytes);
+        }
+
+        let mut vm = Self::new(ProgramOptions::new(cfv, ptr_len), mem_pool, execution_index);
+        vm.stack = stack;
+        vm.call_stack = call_stack;
+        vm.memory_policy = memory_policy;
+        vm.breakpoints = breakpoints;
+        vm.resume_breakpoint = resume_breakpoint;
+        vm.args = args;
+        vm.env = env;
+        vm.exit_code = exit_code;
+
+        let mut ctx = ExecutionContext::new();
+        ctx.ext_a = ext_a;
+        ctx.ext_1 = ext_1;
+        ctx.heap = heap;
+
+        Ok((vm, ctx))
+    }
+}
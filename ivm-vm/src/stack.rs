@@ -0,0 +1,90 @@
+//! The VM stack.
+
+/// The stack of a [crate::VmInstance].
+///
+/// Entries are copied onto the stack when pushed, so they stay valid regardless of later changes to
+/// the memory pool. All entries share one contiguous buffer, and each entry is identified by its
+/// start offset within that buffer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stack {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl Stack {
+    /// Push a copy of the bytes to the top of the stack.
+    #[inline]
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.offsets.push(self.data.len());
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Remove the entry at the top of the stack, then return its bytes.
+    ///
+    /// Returns `None` if the stack is empty.
+    #[inline]
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let start = self.offsets.pop()?;
+        Some(self.data.split_off(start))
+    }
+
+    /// Get the bytes of the entry at the top of the stack.
+    #[inline]
+    pub fn peek(&self) -> Option<&[u8]> {
+        self.offsets.last().map(|start| &self.data[*start..])
+    }
+
+    /// Get the bytes of the entry at the given index, counting from the bottom of the stack.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let start = *self.offsets.get(index)?;
+        let end = self
+            .offsets
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.data.len());
+
+        Some(&self.data[start..end])
+    }
+
+    /// Get an iterator over the entries of the stack, from bottom to top.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.len()).map(|index| self.get(index).unwrap())
+    }
+
+    /// Get the amount of entries on the stack.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns true if the stack contains no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Remove every entry from the stack.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.offsets.clear();
+    }
+
+    /// Create a new stack, able to hold the given amount of entries before reallocating.
+    #[inline]
+    pub fn with_capacity(entries: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            offsets: Vec::with_capacity(entries),
+        }
+    }
+
+    /// Create a new, empty stack.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            offsets: Vec::new(),
+        }
+    }
+}
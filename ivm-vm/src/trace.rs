@@ -0,0 +1,116 @@
+//! Hooks for observing execution.
+//!
+//! A [Tracer] is carried by the [crate::ExecutionEnvironment], and invoked before and after each
+//! instruction and extern call. The default [NoopTracer] does nothing, and compiles away entirely.
+
+use std::io::Write;
+
+use ivm_compile::decode::Decode;
+use ivm_compile::Instruction;
+
+use crate::trap::TrapCause;
+use crate::{ExecutionContext, ExternResult, VmInstance};
+
+/// A trait for observing the execution of a [VmInstance].
+///
+/// Every method does nothing by default.
+pub trait Tracer {
+    /// Called before the instruction at the given execution index is executed.
+    #[inline(always)]
+    fn before_instruction(&mut self, _vm: &VmInstance, _execution_index: usize, _opcode: u8) {}
+
+    /// Called after the instruction starting at the given execution index was executed.
+    ///
+    /// If the instruction trapped, the cause is provided.
+    #[inline(always)]
+    fn after_instruction(
+        &mut self,
+        _vm: &VmInstance,
+        _execution_index: usize,
+        _opcode: u8,
+        _trap: Option<&TrapCause>,
+    ) {
+    }
+
+    /// Called before an extern call is handled by the [crate::ExternMap].
+    #[inline(always)]
+    fn before_extern(&mut self, _vm: &VmInstance, _ctx: &ExecutionContext, _call_id: usize) {}
+
+    /// Called after an extern call was handled by the [crate::ExternMap].
+    #[inline(always)]
+    fn after_extern(
+        &mut self,
+        _vm: &VmInstance,
+        _ctx: &ExecutionContext,
+        _call_id: usize,
+        _result: &ExternResult,
+    ) {
+    }
+}
+
+/// A tracer which does nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopTracer;
+
+impl Tracer for NoopTracer {}
+
+/// A tracer writing a line for every executed instruction, extern call and trap.
+///
+/// Each instruction line contains the execution index, the opcode, the decoded instruction and the
+/// depth of the stack before execution. Errors writing to the output are ignored.
+#[derive(Debug, Default)]
+pub struct WriteTracer<W>
+where
+    W: Write,
+{
+    out: W,
+}
+
+impl<W> WriteTracer<W>
+where
+    W: Write,
+{
+    /// Consume this tracer, returning its output.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Create a new WriteTracer writing to the given output.
+    #[inline]
+    pub const fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W> Tracer for WriteTracer<W>
+where
+    W: Write,
+{
+    fn before_instruction(&mut self, vm: &VmInstance, execution_index: usize, opcode: u8) {
+        let _ = match Instruction::decode(&vm.mem_pool, execution_index, &vm.options) {
+            Ok((instruction, _)) => writeln!(
+                self.out,
+                "{execution_index}: {opcode:02x} {instruction:?} (stack depth {})",
+                vm.stack.len()
+            ),
+            Err(err) => writeln!(self.out, "{execution_index}: {opcode:02x} <{err}>"),
+        };
+    }
+
+    fn after_instruction(
+        &mut self,
+        _vm: &VmInstance,
+        execution_index: usize,
+        _opcode: u8,
+        trap: Option<&TrapCause>,
+    ) {
+        if let Some(cause) = trap {
+            let _ = writeln!(self.out, "{execution_index}: trap: {cause}");
+        }
+    }
+
+    fn before_extern(&mut self, _vm: &VmInstance, _ctx: &ExecutionContext, call_id: usize) {
+        let _ = writeln!(self.out, "  extern call {call_id}");
+    }
+}
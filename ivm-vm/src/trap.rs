@@ -0,0 +1,121 @@
+//! Errors raised while the VM executes bytecode.
+//!
+//! Rather than panicking, the VM stops execution and returns a [VmError] describing which
+//! instruction failed and why. The memory pool is left untouched by the failing instruction, unless
+//! an extern call already performed side effects before failing.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The reason the VM trapped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrapCause {
+    /// The opcode byte did not match any instruction.
+    ///
+    /// See [ivm_compile::byte_id].
+    UnrecognizedInstruction,
+
+    /// The identifier byte of a read operation was not recognized.
+    UnrecognizedReadOperation(u8),
+
+    /// The identifier byte of an integer type was not recognized.
+    UnrecognizedIntType(u8),
+
+    /// An instruction, or one of its operands, accessed memory outside the memory pool.
+    ///
+    /// Contains the start index and the length of the access.
+    OutOfBounds { index: usize, len: usize },
+
+    /// An operand's length did not match the size of the instruction's integer type.
+    OperandSizeMismatch,
+
+    /// The right hand side of a division or remainder was zero.
+    DivisionByZero,
+
+    /// The extern map does not recognize the extern call id.
+    UnrecognizedExtern(usize),
+
+    /// An extern call required ext_a, but no data was loaded.
+    ///
+    /// See [ivm_compile::Instruction::LoadA].
+    ExtANotLoaded,
+
+    /// An instruction or extern call popped more entries than the stack contained.
+    StackUnderflow,
+
+    /// The extern call was denied by a [crate::security::GuardedExternMap].
+    ExternDenied(usize),
+}
+
+impl Display for TrapCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnrecognizedInstruction => write!(f, "unrecognized instruction"),
+            Self::UnrecognizedReadOperation(id) => {
+                write!(f, "unrecognized read operation '{id:02x}'")
+            }
+            Self::UnrecognizedIntType(id) => write!(f, "unrecognized integer type '{id:02x}'"),
+            Self::OutOfBounds { index, len } => write!(
+                f,
+                "memory access out of bounds (index {index}, length {len})"
+            ),
+            Self::OperandSizeMismatch => write!(f, "operand size does not match the integer type"),
+            Self::DivisionByZero => write!(f, "attempted to divide by zero"),
+            Self::UnrecognizedExtern(call_id) => write!(f, "unrecognized extern call '{call_id}'"),
+            Self::ExtANotLoaded => write!(f, "ext_a was not loaded"),
+            Self::StackUnderflow => write!(f, "stack underflow"),
+            Self::ExternDenied(call_id) => write!(f, "extern call '{call_id}' was denied"),
+        }
+    }
+}
+
+/// An error returned when the VM traps during execution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmError {
+    execution_index: usize,
+    opcode: u8,
+    cause: TrapCause,
+}
+
+impl VmError {
+    /// Get the execution index of the instruction that trapped.
+    #[inline]
+    pub const fn execution_index(&self) -> usize {
+        self.execution_index
+    }
+
+    /// Get the opcode of the instruction that trapped.
+    ///
+    /// See [ivm_compile::byte_id].
+    #[inline]
+    pub const fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    /// Get the cause of this error.
+    #[inline]
+    pub const fn cause(&self) -> &TrapCause {
+        &self.cause
+    }
+
+    #[inline]
+    pub const fn new(execution_index: usize, opcode: u8, cause: TrapCause) -> Self {
+        Self {
+            execution_index,
+            opcode,
+            cause,
+        }
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (instruction {:02x} at execution index {})",
+            self.cause, self.opcode, self.execution_index
+        )
+    }
+}
+
+impl Error for VmError {}